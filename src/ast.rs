@@ -0,0 +1,211 @@
+use std::fmt::{Debug, Display, Formatter};
+use std::rc::Rc;
+
+use crate::span::Span;
+use crate::symbol::Symbol;
+use crate::OperatorKind;
+
+/// A piece of syntax of kind `K` together with where it was written.
+#[derive(Clone)]
+pub struct SyntaxNode<K> {
+    pub kind: K,
+    pub span: Span
+}
+
+impl<K> SyntaxNode<K> {
+    pub fn make(kind: K, span: Span) -> Self { Self { kind, span } }
+}
+
+impl<K: Debug> Debug for SyntaxNode<K> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[@{}..{}]: {:?}", self.span.start, self.span.end, self.kind)
+    }
+}
+
+pub type Ident = SyntaxNode<Symbol>;
+pub type Expr = SyntaxNode<ExprKind>;
+pub type Stmt = SyntaxNode<StmtKind>;
+pub type TypeExpr = SyntaxNode<TypeExprKind>;
+
+#[derive(Debug)]
+#[derive(Clone)]
+pub enum ExprKind {
+    Int(i64),
+    Float(f64),
+    Str(String),
+    Char(char),
+    Bool(bool),
+    Ident(Symbol),
+    Unary { op: OperatorKind, operand: Box<Expr> },
+    Binary { op: OperatorKind, lhs: Box<Expr>, rhs: Box<Expr> },
+    Call { callee: Box<Expr>, args: Vec<Expr> },
+    Fn(Rc<FnDef>),
+    /// `{ ... }`, evaluating to its last statement in a scope of its own.
+    Block(Vec<Stmt>),
+    /// `otherwise` is `None` without an `else`; `else if` is an `else` holding just another `If`.
+    If { cond: Box<Expr>, then: Vec<Stmt>, otherwise: Option<Vec<Stmt>> },
+    /// Loops always evaluate to `Unit`, a `break` cannot carry a value out of them.
+    While { cond: Box<Expr>, body: Vec<Stmt> },
+    For { var: Ident, iter: Box<Expr>, body: Vec<Stmt> }
+}
+
+#[derive(Debug)]
+#[derive(Clone)]
+pub enum StmtKind {
+    Let { name: Ident, mutable: bool, ty: Option<TypeExpr>, value: Expr },
+    /// `target = value`, or `target += value` and the like, with `op` the arithmetic applied (`+`).
+    Assign { target: Ident, op: Option<OperatorKind>, value: Expr },
+    Fn(Rc<FnDef>),
+    Return(Option<Expr>),
+    Break,
+    Continue,
+    Expr(Expr)
+}
+
+/// Parameters and body of a `fn`. Shared by every closure created from it,
+/// `name` is `None` for anonymous `fn (x) { ... }` expressions.
+/// `ret` is the `-> <type>` after the parameters, if written.
+#[derive(Debug)]
+pub struct FnDef {
+    pub name:   Option<Ident>,
+    pub params: Vec<Param>,
+    pub ret:    Option<TypeExpr>,
+    pub body:   Vec<Stmt>
+}
+
+/// A parameter with its optional `: <type>`.
+#[derive(Debug)]
+pub struct Param {
+    pub name: Ident,
+    pub ty:   Option<TypeExpr>
+}
+
+/// A type as written in an annotation: a name like `int`, or `fn(<params>) -> <ret>`
+/// where a missing `-> <ret>` means `unit`.
+#[derive(Debug)]
+#[derive(Clone)]
+pub enum TypeExprKind {
+    Named(Symbol),
+    Fn { params: Vec<TypeExpr>, ret: Option<Box<TypeExpr>> }
+}
+
+/// Every statement of a source file, in the order they were written.
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Default)]
+pub struct Program {
+    pub statements: Vec<Stmt>
+}
+
+impl Display for ExprKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExprKind::Int(n) => write!(f, "{}", n),
+            ExprKind::Float(n) => write!(f, "{:?}", n),
+            ExprKind::Str(s) => write!(f, "{:?}", s),
+            ExprKind::Char(c) => write!(f, "{:?}", c),
+            ExprKind::Bool(b) => write!(f, "{}", b),
+            ExprKind::Ident(name) => write!(f, "{}", name),
+            ExprKind::Unary { op, operand } => write!(f, "({}{})", op, operand.kind),
+            ExprKind::Binary { op, lhs, rhs } => write!(f, "({} {} {})", lhs.kind, op, rhs.kind),
+            ExprKind::Call { callee, args } => {
+                write!(f, "{}(", callee.kind)?;
+                for (i, arg) in args.iter().enumerate() {
+                    write!(f, "{}{}", if i > 0 { ", " } else { "" }, arg.kind)?;
+                }
+                write!(f, ")")
+            },
+            ExprKind::Fn(def) => write!(f, "{}", def),
+            ExprKind::Block(statements) => block(f, statements),
+            ExprKind::If { cond, then, otherwise } => {
+                write!(f, "if {} ", cond.kind)?;
+                block(f, then)?;
+                if let Some(otherwise) = otherwise {
+                    write!(f, " else ")?;
+                    block(f, otherwise)?;
+                }
+                Ok(())
+            },
+            ExprKind::While { cond, body } => {
+                write!(f, "while {} ", cond.kind)?;
+                block(f, body)
+            },
+            ExprKind::For { var, iter, body } => {
+                write!(f, "for {} in {} ", var.kind, iter.kind)?;
+                block(f, body)
+            }
+        }
+    }
+}
+
+fn block(f: &mut Formatter<'_>, statements: &[Stmt]) -> std::fmt::Result {
+    write!(f, "{{")?;
+    for stmt in statements {
+        write!(f, " {};", stmt.kind)?;
+    }
+    write!(f, " }}")
+}
+
+impl Display for FnDef {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "fn")?;
+        if let Some(name) = &self.name {
+            write!(f, " {}", name.kind)?;
+        }
+
+        write!(f, "(")?;
+        for (i, param) in self.params.iter().enumerate() {
+            write!(f, "{}{}", if i > 0 { ", " } else { "" }, param.name.kind)?;
+            if let Some(ty) = &param.ty {
+                write!(f, ": {}", ty.kind)?;
+            }
+        }
+        write!(f, ") ")?;
+
+        if let Some(ret) = &self.ret {
+            write!(f, "-> {} ", ret.kind)?;
+        }
+        block(f, &self.body)
+    }
+}
+
+impl Display for TypeExprKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TypeExprKind::Named(name) => write!(f, "{}", name),
+            TypeExprKind::Fn { params, ret } => {
+                write!(f, "fn(")?;
+                for (i, param) in params.iter().enumerate() {
+                    write!(f, "{}{}", if i > 0 { ", " } else { "" }, param.kind)?;
+                }
+                write!(f, ")")?;
+                match ret {
+                    Some(ret) => write!(f, " -> {}", ret.kind),
+                    None => Ok(())
+                }
+            }
+        }
+    }
+}
+
+impl Display for StmtKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StmtKind::Let { name, mutable, ty, value } => {
+                write!(f, "let {}{}", if *mutable { "mut " } else { "" }, name.kind)?;
+                if let Some(ty) = ty {
+                    write!(f, ": {}", ty.kind)?;
+                }
+                write!(f, " be {}", value.kind)
+            },
+            StmtKind::Assign { target, op: Some(op), value } => write!(f, "{} {}= {}", target.kind, op, value.kind),
+            StmtKind::Assign { target, op: None, value } => write!(f, "{} = {}", target.kind, value.kind),
+            StmtKind::Fn(def) => write!(f, "{}", def),
+            StmtKind::Return(Some(value)) => write!(f, "return {}", value.kind),
+            StmtKind::Return(None) => write!(f, "return"),
+            StmtKind::Break => write!(f, "break"),
+            StmtKind::Continue => write!(f, "continue"),
+            StmtKind::Expr(expr) => write!(f, "{}", expr.kind)
+        }
+    }
+}
@@ -0,0 +1,1020 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+
+use crate::ast::{Expr, ExprKind, FnDef, Ident, Program, Stmt, StmtKind, SyntaxNode, TypeExpr, TypeExprKind};
+use crate::error::TypeError;
+use crate::resolver::Resolution;
+use crate::span::Span;
+use crate::OperatorKind;
+
+/// A type as the checker sees it. `Var` is one that is not known yet, an index into `Checker::vars`.
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+pub enum Type {
+    Int,
+    Float,
+    Bool,
+    Str,
+    Char,
+    Range,
+    Unit,
+    Fn(Vec<Type>, Box<Type>),
+    Var(usize)
+}
+
+impl Type {
+    fn is_number(&self) -> bool { matches!(self, Type::Int | Type::Float) }
+}
+
+/// Type names match `Value::type_name`, so checker and runtime errors read alike.
+/// Variables print as `?`, `Checker::describe` names them instead.
+impl Display for Type {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Type::Int => write!(f, "int"),
+            Type::Float => write!(f, "float"),
+            Type::Bool => write!(f, "bool"),
+            Type::Str => write!(f, "string"),
+            Type::Char => write!(f, "char"),
+            Type::Range => write!(f, "range"),
+            Type::Unit => write!(f, "unit"),
+            Type::Fn(params, ret) => {
+                write!(f, "fn(")?;
+                for (i, param) in params.iter().enumerate() {
+                    write!(f, "{}{}", if i > 0 { ", " } else { "" }, param)?;
+                }
+                write!(f, ") -> {}", ret)
+            },
+            Type::Var(_) => write!(f, "?")
+        }
+    }
+}
+
+/// A type variable either stands for the type unification found for it, or is still open.
+/// `level` is the number of generalisable definitions around the place it was made, see `generalize`.
+#[derive(Clone)]
+enum VarState {
+    Bound(Type),
+    Open { level: usize }
+}
+
+/// The type of a declaration. Every use gets fresh variables for `vars`,
+/// which is what lets `fn id(x) { x }` be called with an int and then with a string.
+/// `checks` are the operators of the definition on operands of a type in `vars`,
+/// which every use has to pass again with its own variables.
+#[derive(Clone)]
+struct Scheme {
+    vars:   Vec<usize>,
+    ty:     Type,
+    checks: Vec<Deferred>
+}
+
+impl Scheme {
+    fn mono(ty: Type) -> Self { Self { vars: Vec::new(), ty, checks: Vec::new() } }
+}
+
+/// A rule about an operand whose type was still open when the operator was checked.
+#[derive(Clone)]
+enum Check {
+    Arithmetic(OperatorKind),
+    Ordered(OperatorKind),
+    Negate,
+    /// The loop variable's type, known once the iterable's is.
+    Iterable(Type)
+}
+
+/// `because` is the operator a check came from, when `span` is a use of the generic function
+/// that applies it.
+#[derive(Clone)]
+struct Deferred {
+    check:   Check,
+    ty:      Type,
+    because: Option<Span>,
+    span:    Span
+}
+
+/// Infers a type for every expression and declaration before anything runs, Hindley–Milner style:
+/// unknown types are variables that unification fills in, and named functions, as well as `let`s
+/// bound to a `fn`, are generalised so each use may instantiate them differently.
+/// `: <type>` and `-> <type>` annotations are checked like any other constraint.
+///
+/// Declarations are found through the resolver's `Resolution` and their types kept by the span
+/// of the declared name, so scoping is already taken care of. The checker is stricter than the
+/// runtime in these places:
+///
+/// - An unannotated parameter has one type per call. Where the runtime would mix ints and floats,
+///   arithmetic or an ordering settles that type: `fn f(x) { x + 1.0 }` only takes a float,
+///   and `fn add(a, b) { a + b }` two ints or two floats, but not one of each.
+/// - An `if` without an `else` is always `unit`.
+///
+/// An operator on operands that are unknown inside a generic function, like in
+/// `fn add(a, b) { a + b }`, is checked at each use of the function instead, once the call
+/// settles them. Ones that stay unknown for good are left to the runtime.
+///
+/// Types of top-level names stay in the checker between calls to `check`, for the REPL,
+/// once `commit` keeps them. `saved` is what to go back to otherwise.
+pub struct Checker {
+    vars:     Vec<VarState>,
+    types:    HashMap<Span, Scheme>,
+    saved:    Option<(Vec<VarState>, HashMap<Span, Scheme>)>,
+    uses:     HashMap<Span, Span>,
+    level:    usize,
+    returns:  Vec<(Type, Option<Span>)>,
+    deferred: Vec<Deferred>,
+    errors:   Vec<TypeError>
+}
+
+impl Default for Checker {
+    fn default() -> Self { Self::new() }
+}
+
+impl Checker {
+    pub fn new() -> Self {
+        Self {
+            vars:     Vec::new(),
+            types:    HashMap::new(),
+            saved:    None,
+            uses:     HashMap::new(),
+            level:    0,
+            returns:  Vec::new(),
+            deferred: Vec::new(),
+            errors:   Vec::new()
+        }
+    }
+
+    /// Checks `program`, whose names `resolution` links to their declarations.
+    /// What it learns is forgotten again by the next `check` unless `commit` is called first.
+    pub fn check(&mut self, program: &Program, resolution: &Resolution) -> Vec<TypeError> {
+        if let Some((vars, types)) = self.saved.take() {
+            self.vars = vars;
+            self.types = types;
+        }
+        self.saved = Some((self.vars.clone(), self.types.clone()));
+
+        self.uses = resolution.uses.iter().map(|(used, declaration)| (*used, declaration.span)).collect();
+
+        self.block(&program.statements);
+        self.finish_deferred();
+
+        // Deferred checks come last, but are reported in source order with the rest.
+        let mut errors = std::mem::take(&mut self.errors);
+        errors.sort_by_key(|e| (e.span().file_id, e.span().start));
+        errors
+    }
+
+    /// Keeps the types of the program last checked, once it is known to have run.
+    pub fn commit(&mut self) { self.saved = None; }
+
+    /// The type of a block is that of its last statement.
+    fn block(&mut self, statements: &[Stmt]) -> Type {
+        // Function bodies may use names the block declares further down, so those
+        // need a type before the first statement is checked.
+        for stmt in statements {
+            match &stmt.kind {
+                StmtKind::Let { name, .. } => self.predeclare(name),
+                StmtKind::Fn(def) => self.predeclare(def.name.as_ref().unwrap()),
+                _ => {}
+            }
+        }
+
+        // A definition is checked where it is written, after the ones further down it uses,
+        // so that it sees them generic already. Ones that use each other are checked together.
+        let definitions: Vec<&Stmt> = statements.iter().filter(|stmt| defined(stmt).is_some()).collect();
+        let mut components = self.components(&definitions).into_iter();
+
+        let mut last = Type::Unit;
+        for stmt in statements {
+            last = match defined(stmt) {
+                Some(_) => {
+                    for component in components.next().unwrap() {
+                        let members: Vec<&Stmt> = component.into_iter().map(|i| definitions[i]).collect();
+                        self.definitions(&members);
+                    }
+                    Type::Unit
+                },
+                None => self.statement(stmt)
+            };
+        }
+        last
+    }
+
+    fn predeclare(&mut self, name: &Ident) {
+        let ty = self.fresh();
+        self.types.insert(name.span, Scheme::mono(ty));
+    }
+
+    /// Gives `name` its type. Uses checked before the declaration have to agree with it.
+    fn declare(&mut self, name: &Ident, scheme: Scheme, early: Option<Scheme>) {
+        if let Some(early) = early {
+            let instance = self.instantiate(&scheme, name.span);
+            self.expect(&instance, &early.ty, name, None);
+        }
+
+        self.types.insert(name.span, scheme);
+    }
+
+    /// For each of `definitions`, the groups of them to check when it is reached, see `components`.
+    /// An edge goes from a definition to every one its value uses.
+    fn components(&self, definitions: &[&Stmt]) -> Vec<Vec<Vec<usize>>> {
+        let index: HashMap<Span, usize> = definitions.iter().enumerate()
+            .map(|(i, stmt)| (defined(stmt).unwrap().span, i))
+            .collect();
+
+        let edges: Vec<Vec<usize>> = definitions.iter().map(|stmt| {
+            let mut used = Vec::new();
+            match &stmt.kind {
+                StmtKind::Fn(def) => names_used(&def.body, &mut used),
+                StmtKind::Let { value, .. } => names_used_in(value, &mut used),
+                _ => unreachable!("only `fn`s and `let`s are definitions")
+            }
+            used.iter()
+                .filter_map(|span| self.uses.get(span).and_then(|declared| index.get(declared)).copied())
+                .collect()
+        }).collect();
+
+        components(&edges)
+    }
+
+    /// Checks definitions that use each other. Inside their values they are not generic yet,
+    /// after that all of them are.
+    fn definitions(&mut self, members: &[&Stmt]) {
+        let checks = self.deferred.len();
+        self.level+=1;
+        let mut owns = Vec::with_capacity(members.len());
+        for stmt in members {
+            let own = self.fresh();
+            let early = self.types.insert(defined(stmt).unwrap().span, Scheme::mono(own.clone()));
+            owns.push((own, early));
+        }
+
+        let mut found = Vec::with_capacity(members.len());
+        for (stmt, (own, _)) in members.iter().zip(&owns) {
+            let ty = match &stmt.kind {
+                StmtKind::Fn(def) => self.function(def, *stmt),
+                StmtKind::Let { ty, value, .. } => {
+                    let annotation = ty.as_ref().map(|ty| (self.annotated(ty), ty.span));
+                    let found = self.expression(value);
+                    match annotation {
+                        Some((expected, span)) => {
+                            self.expect(&found, &expected, value, Some(span));
+                            expected
+                        },
+                        None => found
+                    }
+                },
+                _ => unreachable!("only `fn`s and `let`s are definitions")
+            };
+            self.expect(&ty, own, defined(stmt).unwrap(), None);
+            found.push(ty);
+        }
+        self.level-=1;
+
+        for ((stmt, (_, early)), ty) in members.iter().zip(owns).zip(found) {
+            let scheme = self.generalize(&ty, checks);
+            self.declare(defined(stmt).unwrap(), scheme, early);
+        }
+    }
+
+    /// Declarations, assignments and jumps are `unit`. A jump never finishes, so it
+    /// gets a fresh variable that fits whatever a block ending in it has to be.
+    fn statement(&mut self, stmt: &Stmt) -> Type {
+        match &stmt.kind {
+            StmtKind::Let { name, ty, value, .. } => {
+                let annotation = ty.as_ref().map(|ty| (self.annotated(ty), ty.span));
+                let found = self.expression(value);
+                if let Some((expected, span)) = &annotation {
+                    self.expect(&found, expected, value, Some(*span));
+                }
+
+                let early = self.types.remove(&name.span);
+                self.declare(name, Scheme::mono(annotation.map_or(found, |(ty, _)| ty)), early);
+                Type::Unit
+            },
+            // Checked by `block`, see `definitions`.
+            StmtKind::Fn(_) => Type::Unit,
+            StmtKind::Assign { target, op, value } => {
+                let declared = self.uses.get(&target.span).copied();
+                let expected = self.use_of(target);
+                let found = self.expression(value);
+
+                match op {
+                    Some(op) => {
+                        let result = self.arithmetic(*op, &expected, &found, stmt);
+                        self.expect(&result, &expected, stmt, declared);
+                    },
+                    None => self.expect(&found, &expected, value, declared)
+                }
+                Type::Unit
+            },
+            StmtKind::Return(value) => {
+                let (expected, because) = self.returns.last().cloned().expect("the parser only allows `return` in functions");
+                let found = value.as_ref().map_or(Type::Unit, |value| self.expression(value));
+
+                match value {
+                    Some(value) => self.expect(&found, &expected, value, because),
+                    None => self.expect(&found, &expected, stmt, because)
+                }
+                self.fresh()
+            },
+            StmtKind::Break | StmtKind::Continue => self.fresh(),
+            StmtKind::Expr(expr) => self.expression(expr)
+        }
+    }
+
+    /// The type of `def`. Its value is that of the last statement of the body or of a `return`,
+    /// both of which have to fit the `-> <type>` if there is one. `node` is where `def` was written.
+    fn function<K>(&mut self, def: &FnDef, node: &SyntaxNode<K>) -> Type {
+        let mut params = Vec::with_capacity(def.params.len());
+        for param in &def.params {
+            let ty = match &param.ty {
+                Some(ty) => self.annotated(ty),
+                None => self.fresh()
+            };
+            self.types.insert(param.name.span, Scheme::mono(ty.clone()));
+            params.push(ty);
+        }
+
+        let (ret, because) = match &def.ret {
+            Some(ty) => (self.annotated(ty), Some(ty.span)),
+            None => (self.fresh(), None)
+        };
+
+        self.returns.push((ret.clone(), because));
+        let body = self.block(&def.body);
+        self.returns.pop();
+
+        match def.body.last() {
+            Some(last) => self.expect(&body, &ret, last, because),
+            None => self.expect(&body, &ret, node, because)
+        }
+
+        Type::Fn(params, Box::new(ret))
+    }
+
+    fn expression(&mut self, expr: &Expr) -> Type {
+        match &expr.kind {
+            ExprKind::Int(_) => Type::Int,
+            ExprKind::Float(_) => Type::Float,
+            ExprKind::Str(_) => Type::Str,
+            ExprKind::Char(_) => Type::Char,
+            ExprKind::Bool(_) => Type::Bool,
+            ExprKind::Ident(_) => self.use_of(expr),
+            ExprKind::Unary { op, operand } => {
+                let found = self.expression(operand);
+                self.unary(*op, &found, expr)
+            },
+            ExprKind::Binary { op: op @ (OperatorKind::And | OperatorKind::Or), lhs, rhs } => {
+                let (lhs, rhs) = (self.expression(lhs), self.expression(rhs));
+                if !(self.unify(&lhs, &Type::Bool) && self.unify(&rhs, &Type::Bool)) {
+                    self.operator(*op, &lhs, Some(&rhs), expr);
+                }
+                Type::Bool
+            },
+            ExprKind::Binary { op, lhs, rhs } => {
+                let (lhs, rhs) = (self.expression(lhs), self.expression(rhs));
+
+                match op {
+                    OperatorKind::Plus | OperatorKind::Minus | OperatorKind::Mul | OperatorKind::Div =>
+                        self.arithmetic(*op, &lhs, &rhs, expr),
+                    OperatorKind::Range => {
+                        if !(self.unify(&lhs, &Type::Int) && self.unify(&rhs, &Type::Int)) {
+                            self.operator(*op, &lhs, Some(&rhs), expr);
+                        }
+                        Type::Range
+                    },
+                    _ => self.comparison(*op, &lhs, &rhs, expr)
+                }
+            },
+            ExprKind::Call { callee, args } => {
+                let callee_ty = self.expression(callee);
+                let arg_types: Vec<Type> = args.iter().map(|arg| self.expression(arg)).collect();
+
+                match self.resolve(&callee_ty) {
+                    Type::Fn(params, ret) => {
+                        if params.len() != args.len() {
+                            self.errors.push(TypeError::ArityMismatch {
+                                expected: params.len(),
+                                found:    args.len(),
+                                span:     expr.span
+                            });
+                            return *ret;
+                        }
+
+                        for ((param, arg_ty), arg) in params.iter().zip(&arg_types).zip(args) {
+                            self.expect(arg_ty, param, arg, None);
+                        }
+                        *ret
+                    },
+                    callee_ty @ Type::Var(_) => {
+                        let ret = self.fresh();
+                        self.expect(&callee_ty, &Type::Fn(arg_types, Box::new(ret.clone())), callee, None);
+                        ret
+                    },
+                    other => {
+                        let found = self.describe(&[&other]).remove(0);
+                        self.errors.push(TypeError::NotCallable { found, span: expr.span });
+                        self.fresh()
+                    }
+                }
+            },
+            ExprKind::Fn(def) => self.function(def, expr),
+            ExprKind::Block(statements) => self.block(statements),
+            ExprKind::If { cond, then, otherwise } => {
+                let found = self.expression(cond);
+                self.expect(&found, &Type::Bool, cond, None);
+
+                let then_ty = self.block(then);
+                let Some(otherwise) = otherwise else {
+                    return Type::Unit;
+                };
+
+                let otherwise_ty = self.block(otherwise);
+                match otherwise.last() {
+                    Some(last) => self.expect(&otherwise_ty, &then_ty, last, None),
+                    None => self.expect(&otherwise_ty, &then_ty, expr, None)
+                }
+                then_ty
+            },
+            ExprKind::While { cond, body } => {
+                let found = self.expression(cond);
+                self.expect(&found, &Type::Bool, cond, None);
+
+                self.loop_body(body)
+            },
+            ExprKind::For { var, iter, body } => {
+                let found = self.expression(iter);
+
+                let item = match self.resolve(&found) {
+                    Type::Range => Type::Int,
+                    Type::Str => Type::Char,
+                    found @ Type::Var(_) => {
+                        let item = self.fresh();
+                        self.defer(Check::Iterable(item.clone()), found, iter);
+                        item
+                    },
+                    other => {
+                        let found = self.describe(&[&other]).remove(0);
+                        self.errors.push(TypeError::NotIterable { found, span: iter.span });
+                        self.fresh()
+                    }
+                };
+                self.types.insert(var.span, Scheme::mono(item));
+
+                self.loop_body(body)
+            }
+        }
+    }
+
+    /// A loop is `unit`, whether it runs out or a `break` leaves it.
+    fn loop_body(&mut self, body: &[Stmt]) -> Type {
+        self.block(body);
+        Type::Unit
+    }
+
+    /// The type of the declaration `node` uses, with fresh variables if it is generic.
+    fn use_of<K>(&mut self, node: &SyntaxNode<K>) -> Type {
+        let scheme = self.uses.get(&node.span).and_then(|declared| self.types.get(declared)).cloned();
+
+        match scheme {
+            Some(scheme) => self.instantiate(&scheme, node.span),
+            None => self.fresh()
+        }
+    }
+
+    /// `-` takes numbers, `!` bools.
+    fn unary<K>(&mut self, op: OperatorKind, found: &Type, node: &SyntaxNode<K>) -> Type {
+        match (op, self.resolve(found)) {
+            (OperatorKind::Minus, ty) if ty.is_number() => ty,
+            (OperatorKind::Minus, ty @ Type::Var(_)) => {
+                self.defer(Check::Negate, ty.clone(), node);
+                ty
+            },
+            (OperatorKind::Not, ty) if self.unify(&ty, &Type::Bool) => Type::Bool,
+            (_, ty) => {
+                self.operator(op, &ty, None, node);
+                self.fresh()
+            }
+        }
+    }
+
+    /// Same rules as at runtime: integers stay integers, a float operand makes a float
+    /// and `+` also joins strings. An open operand takes the type of the other one.
+    fn arithmetic<K>(&mut self, op: OperatorKind, lhs: &Type, rhs: &Type, node: &SyntaxNode<K>) -> Type {
+        let valid = |ty: &Type| ty.is_number() || (op == OperatorKind::Plus && *ty == Type::Str);
+
+        match (self.resolve(lhs), self.resolve(rhs)) {
+            (Type::Int, Type::Int) => Type::Int,
+            (lhs, rhs) if lhs.is_number() && rhs.is_number() => Type::Float,
+            (Type::Str, Type::Str) if op == OperatorKind::Plus => Type::Str,
+            (lhs @ Type::Var(_), rhs @ Type::Var(_)) => {
+                self.unify(&lhs, &rhs);
+                self.defer(Check::Arithmetic(op), lhs.clone(), node);
+                lhs
+            },
+            (var @ Type::Var(_), ty) | (ty, var @ Type::Var(_)) if valid(&ty) => {
+                self.unify(&var, &ty);
+                ty
+            },
+            (lhs, rhs) => {
+                self.operator(op, &lhs, Some(&rhs), node);
+                self.fresh()
+            }
+        }
+    }
+
+    /// `==` and `!=` take two values of the same type, the orderings numbers, strings or chars.
+    /// Integers and floats compare with each other.
+    fn comparison<K>(&mut self, op: OperatorKind, lhs: &Type, rhs: &Type, node: &SyntaxNode<K>) -> Type {
+        let ordered = !matches!(op, OperatorKind::EqEq | OperatorKind::NotEq);
+        let orderable = |ty: &Type| ty.is_number() || matches!(ty, Type::Str | Type::Char);
+
+        match (self.resolve(lhs), self.resolve(rhs)) {
+            (lhs, rhs) if lhs.is_number() && rhs.is_number() => {},
+            (lhs @ Type::Var(_), rhs @ Type::Var(_)) => {
+                self.unify(&lhs, &rhs);
+                if ordered {
+                    self.defer(Check::Ordered(op), lhs, node);
+                }
+            },
+            (lhs, rhs) if !ordered => {
+                if !self.unify(&lhs, &rhs) {
+                    self.operator(op, &lhs, Some(&rhs), node);
+                }
+            },
+            (var @ Type::Var(_), ty) | (ty, var @ Type::Var(_)) if orderable(&ty) => {
+                self.unify(&var, &ty);
+            },
+            (Type::Str, Type::Str) | (Type::Char, Type::Char) => {},
+            (lhs, rhs) => self.operator(op, &lhs, Some(&rhs), node)
+        }
+
+        Type::Bool
+    }
+
+    fn defer<K>(&mut self, check: Check, ty: Type, node: &SyntaxNode<K>) {
+        self.deferred.push(Deferred { check, ty, because: None, span: node.span });
+    }
+
+    /// Runs the checks left for operands that were open, now that the whole program is unified.
+    /// Ones still open could be anything at runtime and are let through.
+    fn finish_deferred(&mut self) {
+        let (iterables, others): (Vec<_>, Vec<_>) = std::mem::take(&mut self.deferred).into_iter()
+            .partition(|d| matches!(d.check, Check::Iterable(_)));
+
+        // Iterables go first, since they may settle the type of a loop variable other checks look at.
+        for deferred in iterables.into_iter().chain(others) {
+            let Deferred { check, ty, because, span } = deferred;
+            let ty = self.resolve(&ty);
+
+            if matches!(ty, Type::Var(_)) {
+                continue;
+            }
+
+            match check {
+                Check::Arithmetic(op) if !(ty.is_number() || (op == OperatorKind::Plus && ty == Type::Str)) => {
+                    let found = self.describe(&[&ty]).remove(0);
+                    self.errors.push(TypeError::Operator { op, lhs: found.clone(), rhs: Some(found), because, span });
+                },
+                Check::Ordered(op) if !(ty.is_number() || matches!(ty, Type::Str | Type::Char)) => {
+                    let found = self.describe(&[&ty]).remove(0);
+                    self.errors.push(TypeError::Operator { op, lhs: found.clone(), rhs: Some(found), because, span });
+                },
+                Check::Negate if !ty.is_number() => {
+                    let found = self.describe(&[&ty]).remove(0);
+                    self.errors.push(TypeError::Operator { op: OperatorKind::Minus, lhs: found, rhs: None, because, span });
+                },
+                Check::Iterable(item) => {
+                    let expected = match ty {
+                        Type::Range => Type::Int,
+                        Type::Str => Type::Char,
+                        _ => {
+                            let found = self.describe(&[&ty]).remove(0);
+                            self.errors.push(TypeError::NotIterable { found, span });
+                            continue;
+                        }
+                    };
+                    if !self.unify(&item, &expected) {
+                        let names = self.describe(&[&expected, &item]);
+                        self.errors.push(TypeError::Mismatch {
+                            expected: names[0].clone(),
+                            found:    names[1].clone(),
+                            because:  None,
+                            span
+                        });
+                    }
+                },
+                _ => {}
+            }
+        }
+    }
+
+    /// The type an annotation stands for; unknown names are reported and left open.
+    fn annotated(&mut self, ty: &TypeExpr) -> Type {
+        match &ty.kind {
+            TypeExprKind::Named(name) => match &*name.name() {
+                "int" => Type::Int,
+                "float" => Type::Float,
+                "bool" => Type::Bool,
+                "string" => Type::Str,
+                "char" => Type::Char,
+                "range" => Type::Range,
+                "unit" => Type::Unit,
+                _ => {
+                    self.errors.push(TypeError::UnknownType {
+                        name: name.to_string(),
+                        span: ty.span
+                    });
+                    self.fresh()
+                }
+            },
+            TypeExprKind::Fn { params, ret } => {
+                let params = params.iter().map(|param| self.annotated(param)).collect();
+                let ret = ret.as_ref().map_or(Type::Unit, |ret| self.annotated(ret));
+                Type::Fn(params, Box::new(ret))
+            }
+        }
+    }
+
+    /// Unifies `found` with `expected`, or reports that `node` has the wrong type.
+    /// `because` points at what made `expected` the expected type, like an annotation.
+    fn expect<K>(&mut self, found: &Type, expected: &Type, node: &SyntaxNode<K>, because: Option<Span>) {
+        if self.unify(found, expected) {
+            return;
+        }
+
+        let names = self.describe(&[expected, found]);
+        self.errors.push(TypeError::Mismatch {
+            expected: names[0].clone(),
+            found:    names[1].clone(),
+            because,
+            span:     node.span
+        });
+    }
+
+    fn operator<K>(&mut self, op: OperatorKind, lhs: &Type, rhs: Option<&Type>, node: &SyntaxNode<K>) {
+        let mut names = self.describe(&[lhs, rhs.unwrap_or(&Type::Unit)]);
+        let rhs = rhs.map(|_| names.pop().unwrap());
+
+        self.errors.push(TypeError::Operator {
+            op,
+            lhs:     names.remove(0),
+            rhs,
+            because: None,
+            span:    node.span
+        });
+    }
+
+    fn fresh(&mut self) -> Type {
+        self.vars.push(VarState::Open { level: self.level });
+        Type::Var(self.vars.len() - 1)
+    }
+
+    /// Follows bound variables until `ty` is either open or not a variable.
+    fn resolve(&self, ty: &Type) -> Type {
+        let mut ty = ty;
+        while let Type::Var(var) = ty {
+            match &self.vars[*var] {
+                VarState::Bound(bound) => ty = bound,
+                VarState::Open { .. } => break
+            }
+        }
+        ty.clone()
+    }
+
+    /// `ty` with every bound variable, also nested ones, replaced by what it stands for.
+    fn substitute(&self, ty: &Type) -> Type {
+        match self.resolve(ty) {
+            Type::Fn(params, ret) => Type::Fn(params.iter().map(|p| self.substitute(p)).collect(), Box::new(self.substitute(&ret))),
+            ty => ty
+        }
+    }
+
+    /// Makes `a` and `b` the same type by binding open variables, `false` if they cannot be.
+    fn unify(&mut self, a: &Type, b: &Type) -> bool {
+        match (self.resolve(a), self.resolve(b)) {
+            (Type::Var(a), Type::Var(b)) if a == b => true,
+            (Type::Var(var), ty) | (ty, Type::Var(var)) => self.bind(var, &ty),
+            (Type::Fn(a_params, a_ret), Type::Fn(b_params, b_ret)) => {
+                a_params.len() == b_params.len()
+                    && a_params.iter().zip(&b_params).all(|(a, b)| self.unify(a, b))
+                    && self.unify(&a_ret, &b_ret)
+            },
+            (a, b) => a == b
+        }
+    }
+
+    /// Binds the open `var` to `ty`, unless `ty` contains `var`, which would make it infinite.
+    /// Variables in `ty` move out to `var`'s level, so they are only generalised where `var` is.
+    fn bind(&mut self, var: usize, ty: &Type) -> bool {
+        let VarState::Open { level } = self.vars[var] else {
+            unreachable!("`unify` only binds open variables");
+        };
+
+        if !self.adjust(ty, var, level) {
+            return false;
+        }
+        self.vars[var] = VarState::Bound(ty.clone());
+        true
+    }
+
+    fn adjust(&mut self, ty: &Type, var: usize, level: usize) -> bool {
+        match self.resolve(ty) {
+            Type::Var(other) if other == var => false,
+            Type::Var(other) => {
+                if let VarState::Open { level: other_level } = &mut self.vars[other] {
+                    *other_level = (*other_level).min(level);
+                }
+                true
+            },
+            Type::Fn(params, ret) => params.iter().all(|p| self.adjust(p, var, level)) && self.adjust(&ret, var, level),
+            _ => true
+        }
+    }
+
+    /// Quantifies the variables of `ty` that were made inside the definition being generalised
+    /// and are not tied to anything outside it. The definition's deferred checks start at
+    /// `checks` in `deferred`, the ones on quantified variables go with the scheme.
+    fn generalize(&self, ty: &Type, checks: usize) -> Scheme {
+        let ty = self.substitute(ty);
+        let mut vars = Vec::new();
+        self.open_vars(&ty, &mut vars);
+        vars.retain(|var| matches!(self.vars[*var], VarState::Open { level } if level > self.level));
+
+        let checks = self.deferred[checks..].iter()
+            .filter(|deferred| matches!(self.resolve(&deferred.ty), Type::Var(var) if vars.contains(&var)))
+            .cloned()
+            .collect();
+
+        Scheme { vars, ty, checks }
+    }
+
+    /// The type of a use of `scheme` at `span`, whose checks are deferred again for it.
+    fn instantiate(&mut self, scheme: &Scheme, span: Span) -> Type {
+        let fresh: HashMap<usize, Type> = scheme.vars.iter().map(|var| (*var, self.fresh())).collect();
+
+        fn replace(ty: &Type, fresh: &HashMap<usize, Type>) -> Type {
+            match ty {
+                Type::Var(var) => fresh.get(var).cloned().unwrap_or(Type::Var(*var)),
+                Type::Fn(params, ret) => Type::Fn(params.iter().map(|p| replace(p, fresh)).collect(), Box::new(replace(ret, fresh))),
+                ty => ty.clone()
+            }
+        }
+
+        for deferred in &scheme.checks {
+            let check = match &deferred.check {
+                Check::Iterable(item) => Check::Iterable(replace(&self.substitute(item), &fresh)),
+                check => check.clone()
+            };
+            self.deferred.push(Deferred {
+                check,
+                ty:      replace(&self.substitute(&deferred.ty), &fresh),
+                because: deferred.because.or(Some(deferred.span)),
+                span
+            });
+        }
+
+        replace(&self.substitute(&scheme.ty), &fresh)
+    }
+
+    /// Open variables of an already substituted `ty`, in the order they appear.
+    fn open_vars(&self, ty: &Type, vars: &mut Vec<usize>) {
+        match ty {
+            Type::Var(var) if !vars.contains(var) => vars.push(*var),
+            Type::Fn(params, ret) => {
+                params.iter().for_each(|p| self.open_vars(p, vars));
+                self.open_vars(ret, vars);
+            },
+            _ => {}
+        }
+    }
+
+    /// `types` as they are written in messages. Open variables are named `'a`, `'b` and so on,
+    /// consistently across all of `types`.
+    fn describe(&self, types: &[&Type]) -> Vec<String> {
+        let types: Vec<Type> = types.iter().map(|ty| self.substitute(ty)).collect();
+        let mut vars = Vec::new();
+        types.iter().for_each(|ty| self.open_vars(ty, &mut vars));
+
+        fn show(ty: &Type, vars: &[usize]) -> String {
+            match ty {
+                Type::Var(var) => {
+                    let index = vars.iter().position(|v| v == var).unwrap_or_default();
+                    match char::from_u32('a' as u32 + index as u32).filter(char::is_ascii_lowercase) {
+                        Some(letter) => format!("'{}", letter),
+                        None => format!("'t{}", index)
+                    }
+                },
+                Type::Fn(params, ret) => {
+                    let params: Vec<String> = params.iter().map(|p| show(p, vars)).collect();
+                    format!("fn({}) -> {}", params.join(", "), show(ret, vars))
+                },
+                ty => ty.to_string()
+            }
+        }
+
+        types.iter().map(|ty| show(ty, &vars)).collect()
+    }
+}
+
+/// The name a statement declares generically: that of a named `fn`, or of a `let` of a `fn`
+/// that is not `mut`.
+fn defined(stmt: &Stmt) -> Option<&Ident> {
+    match &stmt.kind {
+        StmtKind::Fn(def) => def.name.as_ref(),
+        StmtKind::Let { name, mutable: false, value: Expr { kind: ExprKind::Fn(_), .. }, .. } => Some(name),
+        _ => None
+    }
+}
+
+/// The strongly connected components of the graph with `edges`, by Tarjan's algorithm.
+/// Searches start from each node in turn, and the components each search finds are listed
+/// under the node it started from, after every component they have an edge to. Every node
+/// is in exactly one component, sorted.
+///
+/// The search keeps its own stack, since a chain of functions each calling the next can be
+/// longer than the thread's stack would allow for recursion.
+fn components(edges: &[Vec<usize>]) -> Vec<Vec<Vec<usize>>> {
+    const UNSEEN: usize = usize::MAX;
+
+    let mut index = vec![UNSEEN; edges.len()];
+    let mut low = vec![0; edges.len()];
+    let mut on_stack = vec![false; edges.len()];
+    let mut stack = Vec::new();
+    let mut next = 0;
+    let mut found = vec![Vec::new(); edges.len()];
+
+    for root in 0..edges.len() {
+        if index[root] != UNSEEN {
+            continue;
+        }
+
+        // Each node being searched, with the next of its edges to follow.
+        let mut path = vec![(root, 0)];
+        index[root] = next;
+        low[root] = next;
+        next+=1;
+        stack.push(root);
+        on_stack[root] = true;
+
+        while let Some(&(node, edge)) = path.last() {
+            if let Some(&target) = edges[node].get(edge) {
+                path.last_mut().unwrap().1+=1;
+
+                if index[target] == UNSEEN {
+                    index[target] = next;
+                    low[target] = next;
+                    next+=1;
+                    stack.push(target);
+                    on_stack[target] = true;
+                    path.push((target, 0));
+                } else if on_stack[target] {
+                    low[node] = low[node].min(index[target]);
+                }
+                continue;
+            }
+
+            path.pop();
+            if let Some(&(parent, _)) = path.last() {
+                low[parent] = low[parent].min(low[node]);
+            }
+
+            if low[node] == index[node] {
+                let mut component = Vec::new();
+                loop {
+                    let member = stack.pop().unwrap();
+                    on_stack[member] = false;
+                    component.push(member);
+                    if member == node {
+                        break;
+                    }
+                }
+                component.sort_unstable();
+                found[root].push(component);
+            }
+        }
+    }
+
+    found
+}
+
+/// Collects the spans of the names `statements` use, also inside nested functions.
+fn names_used(statements: &[Stmt], used: &mut Vec<Span>) {
+    for stmt in statements {
+        match &stmt.kind {
+            StmtKind::Let { value, .. } | StmtKind::Assign { value, .. } | StmtKind::Expr(value) => names_used_in(value, used),
+            StmtKind::Fn(def) => names_used(&def.body, used),
+            StmtKind::Return(value) => value.iter().for_each(|value| names_used_in(value, used)),
+            StmtKind::Break | StmtKind::Continue => {}
+        }
+    }
+}
+
+fn names_used_in(expr: &Expr, used: &mut Vec<Span>) {
+    match &expr.kind {
+        ExprKind::Int(_) | ExprKind::Float(_) | ExprKind::Str(_) | ExprKind::Char(_) | ExprKind::Bool(_) => {},
+        ExprKind::Ident(_) => used.push(expr.span),
+        ExprKind::Unary { operand, .. } => names_used_in(operand, used),
+        ExprKind::Binary { lhs, rhs, .. } => {
+            names_used_in(lhs, used);
+            names_used_in(rhs, used);
+        },
+        ExprKind::Call { callee, args } => {
+            names_used_in(callee, used);
+            args.iter().for_each(|arg| names_used_in(arg, used));
+        },
+        ExprKind::Fn(def) => names_used(&def.body, used),
+        ExprKind::Block(statements) => names_used(statements, used),
+        ExprKind::If { cond, then, otherwise } => {
+            names_used_in(cond, used);
+            names_used(then, used);
+            otherwise.iter().for_each(|otherwise| names_used(otherwise, used));
+        },
+        ExprKind::While { cond: iter, body } | ExprKind::For { iter, body, .. } => {
+            names_used_in(iter, used);
+            names_used(body, used);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::{self, Stage};
+
+    fn errors(text: &str) -> Vec<&'static str> { testing::errors(&[text], Stage::Check).remove(0) }
+
+    #[test]
+    fn annotations() {
+        assert_eq!(errors("let x: int = 1 + 2\nlet s: string = \"a\" + \"b\"\nlet f: fn(int) -> int = fn(n) { n }"),
+                   Vec::<&str>::new());
+        assert_eq!(errors("let x: int be \"s\""), vec!["T0001"]);
+        assert_eq!(errors("fn f() -> int { return \"s\" }"), vec!["T0001"]);
+        assert_eq!(errors("let x: nope = 1"), vec!["T0006"]);
+    }
+
+    #[test]
+    fn generic_functions() {
+        assert_eq!(errors("fn id(x) { x }\nlet a: int = id(1)\nlet b: string = id(\"s\")"), Vec::<&str>::new());
+        assert_eq!(errors("let id = fn(x) { x }\nlet a: int = id(1)\nlet b: string = id(\"s\")"), Vec::<&str>::new());
+        assert_eq!(errors("let mut id = fn(x) { x }\nid(1)\nid(\"s\")"), vec!["T0001"]);
+    }
+
+    #[test]
+    fn definitions_in_dependency_order() {
+        // `id` is generic where `g` and `h` use it, even though it comes after them.
+        assert_eq!(errors("fn g() { id(1) + 1 }\nfn h() { id(\"s\") + \"t\" }\nfn id(x) { x }"), Vec::<&str>::new());
+        assert_eq!(errors("fn g() { id(1) + 1 }\nlet id = fn(x) { x }\nfn h() { id(\"s\") + \"t\" }"), Vec::<&str>::new());
+        // Functions that call each other are only generic after both are checked.
+        assert_eq!(errors("fn even(n) { if n == 0 { true } else { odd(n - 1) } }\n\
+                           fn odd(n) { if n == 0 { false } else { even(n - 1) } }\nlet b: bool = even(4)"),
+                   Vec::<&str>::new());
+        assert_eq!(errors("fn a(x) { b(x) }\nfn b(x) { a(1)\na(\"s\") }"), vec!["T0001"]);
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(errors("let x: float = 1 + 2.5"), Vec::<&str>::new());
+        // A parameter takes one number type per call, see the `Checker` docs.
+        assert_eq!(errors("fn f(x) { x + 1.0 }\nf(1)"), vec!["T0001"]);
+        assert_eq!(errors("fn add(a, b) { a + b }\nadd(1, 2.0)"), vec!["T0001"]);
+        assert_eq!(errors("fn add(a, b) { a + b }\nadd(1, 2)\nadd(1.5, 2.5)\nadd(\"a\", \"b\")"), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn operators_and_calls() {
+        assert_eq!(errors("\"a\" - 1"), vec!["T0002"]);
+        assert_eq!(errors("1()"), vec!["T0003"]);
+        assert_eq!(errors("fn f(a) { a }\nf(1, 2)"), vec!["T0004"]);
+        assert_eq!(errors("for c in 1 { }"), vec!["T0005"]);
+        assert_eq!(errors("if true { 1 } else { \"s\" }"), vec!["T0001"]);
+        // Reported in source order, even though the first one is only found at the end.
+        assert_eq!(errors("let mut g = fn(x) { x < x }\ng(true)\nlet n: int = \"s\""), vec!["T0002", "T0001"]);
+    }
+
+    #[test]
+    fn operators_of_generic_functions() {
+        assert_eq!(errors("fn add(a, b) { a + b }\nadd(true, false)"), vec!["T0002"]);
+        assert_eq!(errors("fn add(a, b) { a + b }\nfn g() { }\nadd(g, g)"), vec!["T0002"]);
+        assert_eq!(errors("fn add(a, b) { a + b }\nfn twice(x) { add(x, x) }\ntwice(1)\ntwice('c')"), vec!["T0002"]);
+        assert_eq!(errors("fn neg(x) { -x }\nneg(\"s\")"), vec!["T0002"]);
+        assert_eq!(errors("fn less(a, b) { a < b }\nless('a', 'b')\nless(true, false)"), vec!["T0002"]);
+        assert_eq!(errors("fn each(xs) { for x in xs { } }\neach(\"ab\")\neach(1)"), vec!["T0005"]);
+        // Never settled, so left to the runtime.
+        assert_eq!(errors("fn add(a, b) { a + b }\nfn f(x) { add(x, x) }"), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn loops_are_unit() {
+        assert_eq!(errors("let mut i = 0\nlet x: unit = while i < 3 { i += 1\nif i == 2 { break } }"), Vec::<&str>::new());
+        assert_eq!(errors("let x = for c in 0..3 { }\nx + 1"), vec!["T0002"]);
+        assert_eq!(errors("let c: char = for c in \"ab\" { c }"), vec!["T0001"]);
+    }
+
+    #[test]
+    fn failed_programs_are_forgotten() {
+        assert_eq!(testing::errors(&["let mut g = fn(v) { v }", "let q: string = g(1)", "let s: string = g(\"s\")"], Stage::Check),
+                   vec![vec![], vec!["T0001"], vec![]]);
+        assert_eq!(testing::errors(&["let mut g = fn(v) { v }", "let n: int = g(1)", "g(\"s\")"], Stage::Check),
+                   vec![vec![], vec![], vec!["T0001"]]);
+    }
+}
@@ -0,0 +1,187 @@
+use std::fmt::Write;
+
+use unicode_width::UnicodeWidthStr;
+
+use crate::span::{SourceMap, Span};
+
+const RED: &str = "\x1b[1;31m";
+const BLUE: &str = "\x1b[1;34m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+/// What a tab in a source line is printed as. A terminal would move to its next tab stop,
+/// which the underlines below the line could not know about.
+const TAB: &str = "    ";
+
+/// Text attached to a span. Primary labels are underlined with `^`, secondary ones with `-`.
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct Label {
+    span:    Span,
+    message: String,
+    primary: bool
+}
+
+/// A message about the source plus everything needed to point at it, rustc style:
+///
+/// ```text
+/// error[P0001]: expected Word after "let", found "1" (Numeric)
+///  --> example.rt:5:5
+///   |
+/// 5 | let 1 be hex
+///   | --- after this
+///   |     ^ expected Word
+///   |
+///   = note: bindings are written as `let <name> be <value>` or `let <name> = <value>`
+/// ```
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct Diagnostic {
+    code:     &'static str,
+    message:  String,
+    span:     Span,
+    labels:   Vec<Label>,
+    notes:    Vec<String>
+}
+
+impl Diagnostic {
+    /// `span` is what the header's position points at; rows and columns are only worked out by `render`.
+    pub fn error(code: &'static str, message: String, span: Span) -> Self {
+        Self { code, message, span, labels: Vec::new(), notes: Vec::new() }
+    }
+
+    pub fn with_label(mut self, span: Span, message: &str) -> Self {
+        self.labels.push(Label { span, message: message.to_string(), primary: true });
+        self
+    }
+
+    pub fn with_secondary(mut self, span: Span, message: &str) -> Self {
+        self.labels.push(Label { span, message: message.to_string(), primary: false });
+        self
+    }
+
+    pub fn with_note(mut self, note: &str) -> Self {
+        self.notes.push(note.to_string());
+        self
+    }
+
+    /// Renders the header, every labelled source line and the notes.
+    /// `colour` switches ANSI escape codes on.
+    pub fn render(&self, sources: &SourceMap, colour: bool) -> String {
+        let paint = |code: &'static str, text: &str| -> String {
+            if colour { format!("{}{}{}", code, text, RESET) } else { text.to_string() }
+        };
+
+        let mut out = String::new();
+        let head = format!("error[{}]", self.code);
+        let _ = writeln!(out, "{}{}", paint(RED, &head), paint(BOLD, &format!(": {}", self.message)));
+
+        let mut labels = self.labels.clone();
+        labels.sort_by_key(|l| (l.span.file_id, l.span.start, !l.primary));
+
+        let rows = labels.iter()
+            .map(|l| sources.get(l.span.file_id).pos(l.span.start).row)
+            .max()
+            .unwrap_or(0);
+        let width = rows.to_string().len();
+        let gutter = paint(BLUE, &format!("{} |", " ".repeat(width)));
+
+        let pos = sources.get(self.span.file_id).pos(self.span.start);
+        let _ = writeln!(out, "{}{} {}", " ".repeat(width), paint(BLUE, "-->"), pos);
+        if !labels.is_empty() {
+            let _ = writeln!(out, "{}", gutter);
+        }
+
+        let mut l_id = 0;
+        while l_id < labels.len() {
+            let file = sources.get(labels[l_id].span.file_id);
+            let row = file.pos(labels[l_id].span.start).row;
+            let line = file.line(row);
+
+            let _ = writeln!(out, "{} {}",
+                             paint(BLUE, &format!("{:>width$} |", row, width = width)), line.replace('\t', TAB));
+
+            // Every label starting on this row gets its own underline, in source order.
+            while l_id < labels.len()
+                && labels[l_id].span.file_id == file.id
+                && file.pos(labels[l_id].span.start).row == row {
+                let label = &labels[l_id];
+                let start = file.pos(label.span.start);
+                let end = file.pos(label.span.end);
+                // Measured in terminal cells as printed, so tabs and wide characters before
+                // or under the label don't shift it.
+                let before = &line[..start.byte_col - 1];
+                let under = if end.row == row {
+                    &line[start.byte_col - 1..end.byte_col - 1]
+                } else {
+                    &line[start.byte_col - 1..]
+                };
+                let len = under.replace('\t', TAB).width().max(1);
+
+                let (mark, code) = if label.primary { ('^', RED) } else { ('-', BLUE) };
+                let underline = mark.to_string().repeat(len);
+                let _ = writeln!(out, "{} {}{} {}",
+                                 gutter, " ".repeat(before.replace('\t', TAB).width()),
+                                 paint(code, &underline), paint(code, &label.message));
+                l_id+=1;
+            }
+        }
+
+        if !self.notes.is_empty() {
+            let _ = writeln!(out, "{}", gutter);
+        }
+        for note in &self.notes {
+            let _ = writeln!(out, "{}{} {}",
+                             " ".repeat(width + 1), paint(BLUE, "="),
+                             format_args!("{}: {}", paint(BOLD, "note"), note));
+        }
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render() {
+        let mut sources = SourceMap::new();
+        let file_id = sources.add("test.rt", "let x = 1\nlet 名 = y + 2");
+
+        let diagnostic = Diagnostic::error("S0001", "cannot find `y`".to_string(), Span::make(file_id, 20, 21))
+            .with_secondary(Span::make(file_id, 10, 13), "in this binding")
+            .with_label(Span::make(file_id, 20, 21), "not found")
+            .with_note("names are declared with `let`");
+
+        assert_eq!(diagnostic.render(&sources, false), "\
+error[S0001]: cannot find `y`
+ --> test.rt:2:9
+  |
+2 | let 名 = y + 2
+  | --- in this binding
+  |          ^ not found
+  |
+  = note: names are declared with `let`
+");
+    }
+
+    #[test]
+    fn tabs() {
+        let mut sources = SourceMap::new();
+        let file_id = sources.add("test.rt", "\tlet x =\t\ty");
+
+        let diagnostic = Diagnostic::error("S0001", "cannot find `y`".to_string(), Span::make(file_id, 10, 11))
+            .with_secondary(Span::make(file_id, 8, 10), "tabs")
+            .with_label(Span::make(file_id, 10, 11), "not found");
+
+        assert_eq!(diagnostic.render(&sources, false), "\
+error[S0001]: cannot find `y`
+ --> test.rt:1:11
+  |
+1 |     let x =        y
+  |            -------- tabs
+  |                    ^ not found
+");
+    }
+}
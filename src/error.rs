@@ -0,0 +1,521 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+use crate::diagnostic::Diagnostic;
+use crate::resolver::DeclarationKind;
+use crate::span::Span;
+use crate::{OperatorKind, TokenKind};
+
+/// Everything `lex` can refuse to turn into a token.
+#[derive(Debug)]
+#[derive(Clone)]
+pub enum LexError {
+    UnexpectedChar { ch: char, span: Span },
+    InvalidDigit { ch: char, radix: &'static str, span: Span },
+    MissingDigits { what: &'static str, span: Span },
+    InvalidSuffix { suffix: String, float: bool, span: Span },
+    Unterminated { what: &'static str, span: Span },
+    InvalidEscape { escape: String, span: Span },
+    CharLength { span: Span },
+    UnexpectedFraction { why: &'static str, span: Span }
+}
+
+impl LexError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            LexError::UnexpectedChar { .. } => "L0001",
+            LexError::InvalidDigit { .. } => "L0002",
+            LexError::MissingDigits { .. } => "L0003",
+            LexError::InvalidSuffix { .. } => "L0004",
+            LexError::Unterminated { .. } => "L0005",
+            LexError::InvalidEscape { .. } => "L0006",
+            LexError::CharLength { .. } => "L0007",
+            LexError::UnexpectedFraction { .. } => "L0008"
+        }
+    }
+
+    pub fn span(&self) -> Span {
+        match self {
+            LexError::UnexpectedChar { span, .. }
+            | LexError::InvalidDigit { span, .. }
+            | LexError::MissingDigits { span, .. }
+            | LexError::InvalidSuffix { span, .. }
+            | LexError::Unterminated { span, .. }
+            | LexError::InvalidEscape { span, .. }
+            | LexError::CharLength { span, .. }
+            | LexError::UnexpectedFraction { span, .. } => *span
+        }
+    }
+
+    /// Whether the input only stopped too early, i.e. more lines could still complete it.
+    /// Character literals never span lines, so those are not counted.
+    pub fn is_incomplete(&self) -> bool {
+        matches!(self, LexError::Unterminated { what, .. } if *what != "character literal")
+    }
+
+    pub fn to_diagnostic(&self) -> Diagnostic {
+        let diagnostic = Diagnostic::error(self.code(), self.to_string(), self.span());
+
+        match self {
+            LexError::UnexpectedChar { span, .. } =>
+                diagnostic.with_label(*span, "not part of any token"),
+            LexError::InvalidDigit { ch, span, .. } =>
+                diagnostic.with_label(*span, &format!("{:?} is not a valid digit here", ch)),
+            LexError::MissingDigits { span, .. } =>
+                diagnostic.with_label(*span, "needs at least one digit"),
+            LexError::InvalidSuffix { float, span, .. } =>
+                diagnostic.with_label(*span, "unknown or misplaced suffix")
+                          .with_note(if *float {
+                              "floating point literals accept the suffixes `f32` and `f64`"
+                          } else {
+                              "integer literals accept `i8`-`i64` and `u8`-`u64`; decimal ones also `f32` and `f64`"
+                          }),
+            LexError::Unterminated { what, span, .. } =>
+                diagnostic.with_label(*span, &format!("{} starts here", what)),
+            LexError::InvalidEscape { span, .. } =>
+                diagnostic.with_label(*span, "unknown escape")
+                          .with_note("valid escapes are \\n \\r \\t \\0 \\\\ \\\" \\' and \\u{...}"),
+            LexError::CharLength { span, .. } =>
+                diagnostic.with_label(*span, "must contain exactly one character")
+                          .with_note("use double quotes for strings"),
+            LexError::UnexpectedFraction { why, span } =>
+                diagnostic.with_label(*span, why)
+        }
+    }
+}
+
+impl Display for LexError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LexError::UnexpectedChar { ch, .. } =>
+                write!(f, "unexpected character {:?}", ch),
+            LexError::InvalidDigit { ch, radix, .. } =>
+                write!(f, "invalid digit {:?} in {}", ch, radix),
+            LexError::MissingDigits { what, .. } =>
+                write!(f, "expected at least one digit in {}", what),
+            LexError::InvalidSuffix { suffix, .. } =>
+                write!(f, "invalid suffix \"{}\" for number literal", suffix),
+            LexError::Unterminated { what, .. } =>
+                write!(f, "unterminated {}", what),
+            LexError::InvalidEscape { escape, .. } =>
+                write!(f, "invalid escape sequence \"{}\"", escape),
+            LexError::CharLength { .. } =>
+                write!(f, "character literal must contain exactly one character"),
+            LexError::UnexpectedFraction { .. } =>
+                write!(f, "unexpected fraction in number literal")
+        }
+    }
+}
+
+impl Error for LexError {}
+
+/// Everything `parse` can refuse to turn into syntax.
+/// `after`/`prev` describe the token before the offending one, if there is one.
+#[derive(Debug)]
+#[derive(Clone)]
+pub enum ParseError {
+    UnexpectedToken {
+        expected: Vec<TokenKind>,
+        found:    TokenKind,
+        value:    String,
+        after:    Option<String>,
+        span:     Span,
+        prev:     Option<Span>
+    },
+    UnexpectedEof {
+        expected: Vec<TokenKind>,
+        after:    String,
+        span:     Span,
+        prev:     Span
+    },
+    UnclosedDelimiter {
+        open:     String,
+        close:    String,
+        span:     Span,
+        open_at:  Span
+    },
+    InvalidLiteral { text: String, ty: &'static str, span: Span },
+    Misplaced { keyword: String, outside: &'static str, span: Span },
+    ExpectedSymbol { symbol: String, found: Option<String>, span: Span },
+    TooDeep { limit: usize, span: Span },
+    /// `break <value>`, with `span` that of the value.
+    BreakValue { span: Span }
+}
+
+impl ParseError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            ParseError::UnexpectedToken { .. } => "P0001",
+            ParseError::UnexpectedEof { .. } => "P0002",
+            ParseError::UnclosedDelimiter { .. } => "P0003",
+            ParseError::InvalidLiteral { .. } => "P0004",
+            ParseError::Misplaced { .. } => "P0005",
+            ParseError::ExpectedSymbol { .. } => "P0006",
+            ParseError::TooDeep { .. } => "P0007",
+            ParseError::BreakValue { .. } => "P0008"
+        }
+    }
+
+    pub fn span(&self) -> Span {
+        match self {
+            ParseError::UnexpectedToken { span, .. }
+            | ParseError::UnexpectedEof { span, .. }
+            | ParseError::UnclosedDelimiter { span, .. }
+            | ParseError::InvalidLiteral { span, .. }
+            | ParseError::Misplaced { span, .. }
+            | ParseError::ExpectedSymbol { span, .. }
+            | ParseError::TooDeep { span, .. }
+            | ParseError::BreakValue { span } => *span
+        }
+    }
+
+    /// Whether the error is only that the input ended, e.g. after `let x be` or an open `(`.
+    pub fn is_incomplete(&self) -> bool {
+        match self {
+            ParseError::UnexpectedEof { .. } | ParseError::ExpectedSymbol { found: None, .. } => true,
+            // Reported at an empty span right after the last token when nothing follows.
+            ParseError::UnclosedDelimiter { span, .. } => span.start == span.end,
+            _ => false
+        }
+    }
+
+    pub fn to_diagnostic(&self) -> Diagnostic {
+        let (expected, after, span, prev) = match self {
+            ParseError::UnexpectedToken { expected, after, span, prev, .. } =>
+                (expected, after.as_deref(), span, *prev),
+            ParseError::UnexpectedEof { expected, after, span, prev, .. } =>
+                (expected, Some(after.as_str()), span, Some(*prev)),
+            ParseError::UnclosedDelimiter { open, close, span, open_at, .. } =>
+                return Diagnostic::error(self.code(), self.to_string(), self.span())
+                    .with_label(*span, &format!("expected \"{}\"", close))
+                    .with_secondary(*open_at, &format!("unclosed \"{}\"", open)),
+            ParseError::InvalidLiteral { ty, span, .. } =>
+                return Diagnostic::error(self.code(), self.to_string(), self.span())
+                    .with_label(*span, &format!("does not fit in {}", ty))
+                    .with_note("every integer is an `i64` at runtime, a suffix only narrows the range of the literal"),
+            ParseError::Misplaced { outside, span, .. } =>
+                return Diagnostic::error(self.code(), self.to_string(), self.span())
+                    .with_label(*span, &format!("only allowed inside a {}", outside)),
+            ParseError::ExpectedSymbol { symbol, span, .. } =>
+                return Diagnostic::error(self.code(), self.to_string(), self.span())
+                    .with_label(*span, &format!("expected \"{}\"", symbol)),
+            ParseError::TooDeep { limit, span } =>
+                return Diagnostic::error(self.code(), self.to_string(), self.span())
+                    .with_label(*span, &format!("more than {} levels deep here", limit))
+                    .with_note("give parts of it a name with `let` first"),
+            ParseError::BreakValue { span } =>
+                return Diagnostic::error(self.code(), self.to_string(), self.span())
+                    .with_label(*span, "loops are always `unit`")
+                    .with_note("assign the value to a `let mut` declared before the loop instead")
+        };
+
+        let mut diagnostic = Diagnostic::error(self.code(), self.to_string(), self.span())
+            .with_label(*span, &format!("expected {}", kinds(expected)));
+
+        if let Some(prev) = prev {
+            diagnostic = diagnostic.with_secondary(prev, "after this");
+        }
+
+        match after {
+            Some("let" | "mut" | "be" | "=") =>
+                diagnostic.with_note("bindings are written as `let <name> be <value>` or `let <name> = <value>`"),
+            _ => diagnostic
+        }
+    }
+}
+
+fn kinds(expected: &[TokenKind]) -> String {
+    expected.iter().map(|k| format!("{:?}", k)).collect::<Vec<_>>().join(" or ")
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseError::UnexpectedToken { expected, found, value, after: Some(after), .. } =>
+                write!(f, "expected {} after \"{}\", found \"{}\" ({:?})",
+                       kinds(expected), after, value, found),
+            ParseError::UnexpectedToken { expected, found, value, after: None, .. } =>
+                write!(f, "expected {}, found \"{}\" ({:?})", kinds(expected), value, found),
+            ParseError::UnexpectedEof { expected, after, .. } =>
+                write!(f, "expected {} after \"{}\", found end of input", kinds(expected), after),
+            ParseError::UnclosedDelimiter { open, close, .. } =>
+                write!(f, "expected \"{}\" to close \"{}\"", close, open),
+            ParseError::InvalidLiteral { text, ty, .. } =>
+                write!(f, "integer literal \"{}\" is out of range for {}", text, ty),
+            ParseError::Misplaced { keyword, outside, .. } =>
+                write!(f, "\"{}\" outside of a {}", keyword, outside),
+            ParseError::ExpectedSymbol { symbol, found: Some(found), .. } =>
+                write!(f, "expected \"{}\", found \"{}\"", symbol, found),
+            ParseError::ExpectedSymbol { symbol, found: None, .. } =>
+                write!(f, "expected \"{}\", found end of input", symbol),
+            ParseError::TooDeep { .. } => write!(f, "nested too deeply"),
+            ParseError::BreakValue { .. } => write!(f, "\"break\" with a value")
+        }
+    }
+}
+
+impl Error for ParseError {}
+
+/// Names the resolver could not link to a declaration, declared twice in one scope,
+/// or used before something they capture is defined.
+#[derive(Debug)]
+#[derive(Clone)]
+pub enum ResolveError {
+    Undefined { name: String, span: Span },
+    UsedBeforeDefinition { name: String, declared: Span, span: Span },
+    Redefined { name: String, previous: Span, span: Span },
+    Immutable { name: String, kind: DeclarationKind, declared: Span, span: Span },
+    CapturedBeforeDefinition { name: Option<String>, captured: String, declared: Span, span: Span }
+}
+
+impl ResolveError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            ResolveError::Undefined { .. } => "S0001",
+            ResolveError::UsedBeforeDefinition { .. } => "S0002",
+            ResolveError::Redefined { .. } => "S0003",
+            ResolveError::Immutable { .. } => "S0004",
+            ResolveError::CapturedBeforeDefinition { .. } => "S0005"
+        }
+    }
+
+    pub fn span(&self) -> Span {
+        match self {
+            ResolveError::Undefined { span, .. }
+            | ResolveError::UsedBeforeDefinition { span, .. }
+            | ResolveError::Redefined { span, .. }
+            | ResolveError::Immutable { span, .. }
+            | ResolveError::CapturedBeforeDefinition { span, .. } => *span
+        }
+    }
+
+    pub fn to_diagnostic(&self) -> Diagnostic {
+        let diagnostic = Diagnostic::error(self.code(), self.to_string(), self.span());
+
+        match self {
+            ResolveError::Undefined { span, .. } =>
+                diagnostic.with_label(*span, "not declared in any enclosing scope"),
+            ResolveError::UsedBeforeDefinition { declared, span, .. } =>
+                diagnostic.with_label(*span, "used here")
+                          .with_secondary(*declared, "declared later here"),
+            ResolveError::Redefined { previous, span, .. } =>
+                diagnostic.with_label(*span, "declared again here")
+                          .with_secondary(*previous, "first declared here")
+                          .with_note("a name can be declared once per scope; an inner `{ ... }` may shadow it"),
+            ResolveError::Immutable { name, kind: DeclarationKind::Let, declared, span, .. } =>
+                diagnostic.with_label(*span, "assigned here")
+                          .with_secondary(*declared, "declared without `mut` here")
+                          .with_note(&format!("declare it as `let mut {}` to allow assignment", name)),
+            ResolveError::Immutable { name, declared, span, .. } =>
+                diagnostic.with_label(*span, "assigned here")
+                          .with_secondary(*declared, "declared here")
+                          .with_note(&format!("only `let mut` bindings can be assigned to; \
+                                               shadow it with `let mut {0} be {0}` first", name)),
+            ResolveError::CapturedBeforeDefinition { name: Some(name), captured, declared, span } =>
+                diagnostic.with_label(*span, "used here")
+                          .with_secondary(*declared, &format!("\"{}\" is defined later here", captured))
+                          .with_note(&format!("\"{}\" uses \"{}\", so it can only be used once that is defined", name, captured)),
+            ResolveError::CapturedBeforeDefinition { name: None, captured, declared, span } =>
+                diagnostic.with_label(*span, "created here")
+                          .with_secondary(*declared, &format!("\"{}\" is defined later here", captured))
+                          .with_note("only functions declared with `fn` or bound by `let` may use names defined after them")
+        }
+    }
+}
+
+impl Display for ResolveError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ResolveError::Undefined { name, .. } => write!(f, "cannot find \"{}\" in this scope", name),
+            ResolveError::UsedBeforeDefinition { name, .. } => write!(f, "\"{}\" is used before its definition", name),
+            ResolveError::Redefined { name, .. } => write!(f, "\"{}\" is already declared in this scope", name),
+            ResolveError::Immutable { name, kind, .. } => write!(f, "cannot assign to immutable {} \"{}\"", kind, name),
+            ResolveError::CapturedBeforeDefinition { name: Some(name), captured, .. } =>
+                write!(f, "\"{}\" is used before \"{}\", which it captures, is defined", name, captured),
+            ResolveError::CapturedBeforeDefinition { name: None, captured, .. } =>
+                write!(f, "anonymous function uses \"{}\" before it is defined", captured)
+        }
+    }
+}
+
+impl Error for ResolveError {}
+
+/// Everything the type checker rejects before a program runs.
+/// Types are rendered as they would be written in an annotation, e.g. `fn(int) -> 'a`.
+#[derive(Debug)]
+#[derive(Clone)]
+pub enum TypeError {
+    Mismatch { expected: String, found: String, because: Option<Span>, span: Span },
+    /// `because` is the operator inside a generic function, when `span` is where it was used.
+    Operator { op: OperatorKind, lhs: String, rhs: Option<String>, because: Option<Span>, span: Span },
+    NotCallable { found: String, span: Span },
+    ArityMismatch { expected: usize, found: usize, span: Span },
+    NotIterable { found: String, span: Span },
+    UnknownType { name: String, span: Span }
+}
+
+impl TypeError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            TypeError::Mismatch { .. } => "T0001",
+            TypeError::Operator { .. } => "T0002",
+            TypeError::NotCallable { .. } => "T0003",
+            TypeError::ArityMismatch { .. } => "T0004",
+            TypeError::NotIterable { .. } => "T0005",
+            TypeError::UnknownType { .. } => "T0006"
+        }
+    }
+
+    pub fn span(&self) -> Span {
+        match self {
+            TypeError::Mismatch { span, .. }
+            | TypeError::Operator { span, .. }
+            | TypeError::NotCallable { span, .. }
+            | TypeError::ArityMismatch { span, .. }
+            | TypeError::NotIterable { span, .. }
+            | TypeError::UnknownType { span, .. } => *span
+        }
+    }
+
+    pub fn to_diagnostic(&self) -> Diagnostic {
+        let diagnostic = Diagnostic::error(self.code(), self.to_string(), self.span());
+
+        match self {
+            TypeError::Mismatch { expected, found, because, span, .. } => {
+                let diagnostic = diagnostic.with_label(*span, &format!("expected `{}`, found `{}`", expected, found));
+                match because {
+                    Some(because) => diagnostic.with_secondary(*because, "expected because of this"),
+                    None => diagnostic
+                }
+            },
+            TypeError::Operator { because, span, .. } => {
+                let diagnostic = diagnostic.with_label(*span, "unsupported operand types");
+                match because {
+                    Some(because) => diagnostic.with_secondary(*because, "the function applies the operator here"),
+                    None => diagnostic
+                }
+            },
+            TypeError::NotCallable { span, .. } =>
+                diagnostic.with_label(*span, "only functions can be called"),
+            TypeError::ArityMismatch { expected, span, .. } =>
+                diagnostic.with_label(*span, &format!("expected {} argument{}", expected, if *expected == 1 { "" } else { "s" })),
+            TypeError::NotIterable { span, .. } =>
+                diagnostic.with_label(*span, "`for` needs a range or a string here"),
+            TypeError::UnknownType { span, .. } =>
+                diagnostic.with_label(*span, "not a type")
+                          .with_note("types are int, float, bool, string, char, range, unit and fn(<types>) -> <type>")
+        }
+    }
+}
+
+impl Display for TypeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TypeError::Mismatch { expected, found, .. } => write!(f, "mismatched types: expected {}, found {}", expected, found),
+            TypeError::Operator { op, lhs, rhs: Some(rhs), .. } =>
+                write!(f, "cannot apply \"{}\" to {} and {}", op, lhs, rhs),
+            TypeError::Operator { op, lhs, rhs: None, .. } =>
+                write!(f, "cannot apply \"{}\" to {}", op, lhs),
+            TypeError::NotCallable { found, .. } => write!(f, "cannot call a value of type {}", found),
+            TypeError::ArityMismatch { expected, found, .. } =>
+                write!(f, "function takes {} argument(s) but {} were supplied", expected, found),
+            TypeError::NotIterable { found, .. } => write!(f, "cannot iterate over a value of type {}", found),
+            TypeError::UnknownType { name, .. } => write!(f, "unknown type \"{}\"", name)
+        }
+    }
+}
+
+impl Error for TypeError {}
+
+/// Everything that can go wrong while running a parsed program.
+#[derive(Debug)]
+#[derive(Clone)]
+pub enum EvalError {
+    UndefinedName { name: String, span: Span },
+    TypeMismatch { op: OperatorKind, lhs: &'static str, rhs: Option<&'static str>, span: Span },
+    DivisionByZero { span: Span },
+    Overflow { op: OperatorKind, span: Span },
+    NotCallable { found: &'static str, span: Span },
+    ArityMismatch { expected: usize, found: usize, span: Span },
+    StackOverflow { span: Span },
+    NotBool { found: &'static str, context: &'static str, span: Span },
+    NotIterable { found: &'static str, span: Span }
+}
+
+impl EvalError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            EvalError::UndefinedName { .. } => "R0001",
+            EvalError::TypeMismatch { .. } => "R0002",
+            EvalError::DivisionByZero { .. } => "R0003",
+            EvalError::Overflow { .. } => "R0004",
+            EvalError::NotCallable { .. } => "R0005",
+            EvalError::ArityMismatch { .. } => "R0006",
+            EvalError::StackOverflow { .. } => "R0007",
+            EvalError::NotBool { .. } => "R0008",
+            EvalError::NotIterable { .. } => "R0009"
+        }
+    }
+
+    pub fn span(&self) -> Span {
+        match self {
+            EvalError::UndefinedName { span, .. }
+            | EvalError::TypeMismatch { span, .. }
+            | EvalError::DivisionByZero { span, .. }
+            | EvalError::Overflow { span, .. }
+            | EvalError::NotCallable { span, .. }
+            | EvalError::ArityMismatch { span, .. }
+            | EvalError::StackOverflow { span, .. }
+            | EvalError::NotBool { span, .. }
+            | EvalError::NotIterable { span, .. } => *span
+        }
+    }
+
+    pub fn to_diagnostic(&self) -> Diagnostic {
+        let diagnostic = Diagnostic::error(self.code(), self.to_string(), self.span());
+
+        match self {
+            EvalError::UndefinedName { span, .. } =>
+                diagnostic.with_label(*span, "not bound by any earlier `let`"),
+            EvalError::TypeMismatch { span, .. } =>
+                diagnostic.with_label(*span, "unsupported operand types"),
+            EvalError::DivisionByZero { span, .. } =>
+                diagnostic.with_label(*span, "the divisor evaluates to 0"),
+            EvalError::Overflow { span, .. } =>
+                diagnostic.with_label(*span, "result does not fit in a 64-bit integer"),
+            EvalError::NotCallable { span, .. } =>
+                diagnostic.with_label(*span, "only functions can be called"),
+            EvalError::ArityMismatch { expected, span, .. } =>
+                diagnostic.with_label(*span, &format!("expected {} argument{}", expected, if *expected == 1 { "" } else { "s" })),
+            EvalError::StackOverflow { span, .. } =>
+                diagnostic.with_label(*span, "while evaluating this")
+                          .with_note("check for recursion without a base case"),
+            EvalError::NotBool { span, .. } =>
+                diagnostic.with_label(*span, "expected `true` or `false`"),
+            EvalError::NotIterable { span, .. } =>
+                diagnostic.with_label(*span, "`for` needs a range or a string here")
+        }
+    }
+}
+
+impl Display for EvalError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EvalError::UndefinedName { name, .. } => write!(f, "cannot find \"{}\" in this scope", name),
+            EvalError::TypeMismatch { op, lhs, rhs: Some(rhs), .. } =>
+                write!(f, "cannot apply \"{}\" to {} and {}", op, lhs, rhs),
+            EvalError::TypeMismatch { op, lhs, rhs: None, .. } =>
+                write!(f, "cannot apply \"{}\" to {}", op, lhs),
+            EvalError::DivisionByZero { .. } => write!(f, "integer division by zero"),
+            EvalError::Overflow { op, .. } => write!(f, "integer overflow in \"{}\"", op),
+            EvalError::NotCallable { found, .. } => write!(f, "cannot call a value of type {}", found),
+            EvalError::ArityMismatch { expected, found, .. } =>
+                write!(f, "function takes {} argument(s) but {} were supplied", expected, found),
+            EvalError::StackOverflow { .. } => write!(f, "ran out of stack space"),
+            EvalError::NotBool { found, context, .. } => write!(f, "{} must be a bool, found {}", context, found),
+            EvalError::NotIterable { found, .. } => write!(f, "cannot iterate over a value of type {}", found)
+        }
+    }
+}
+
+impl Error for EvalError {}
@@ -0,0 +1,581 @@
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::ast::{Expr, ExprKind, FnDef, Program, Stmt, StmtKind, SyntaxNode};
+use crate::error::EvalError;
+use crate::stack;
+use crate::symbol::Symbol;
+use crate::value::{Closure, Value};
+use crate::OperatorKind;
+
+#[derive(Debug)]
+#[derive(Default)]
+struct Scope {
+    vars:   HashMap<Symbol, Value>,
+    parent: Option<Environment>
+}
+
+/// Names bound in one scope, falling back to the scopes it is nested in.
+/// Cloning shares the scope, which is how closures keep seeing their surroundings.
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Default)]
+pub struct Environment(Rc<RefCell<Scope>>);
+
+impl Environment {
+    pub fn child(&self) -> Self {
+        Environment(Rc::new(RefCell::new(Scope { vars: HashMap::new(), parent: Some(self.clone()) })))
+    }
+
+    pub fn get(&self, name: Symbol) -> Option<Value> {
+        let scope = self.0.borrow();
+
+        match scope.vars.get(&name) {
+            Some(value) => Some(value.clone()),
+            None => scope.parent.as_ref()?.get(name)
+        }
+    }
+
+    pub fn define(&self, name: Symbol, value: Value) { self.0.borrow_mut().vars.insert(name, value); }
+
+    /// Replaces the value of `name` in the innermost scope that binds it.
+    /// Returns `false` if no scope does.
+    pub fn assign(&self, name: Symbol, value: Value) -> bool {
+        let mut scope = self.0.borrow_mut();
+
+        match scope.vars.get_mut(&name) {
+            Some(slot) => {
+                *slot = value;
+                true
+            },
+            None => scope.parent.as_ref().is_some_and(|parent| parent.assign(name, value))
+        }
+    }
+
+    pub fn ptr_eq(&self, other: &Environment) -> bool { Rc::ptr_eq(&self.0, &other.0) }
+
+    /// Moves what `child`, a child of this scope, binds into this scope and returns `true`.
+    /// Returns `false` and leaves both alone if that would change the value of a name visible
+    /// from here while anything but `child` holds on to this scope, like a closure could.
+    fn absorb(&self, child: &Environment) -> bool {
+        // One reference is `self`, another the parent of `child`.
+        let shared = Rc::strong_count(&self.0) > 2;
+        let mut child = child.0.borrow_mut();
+
+        if shared && child.vars.keys().any(|name| self.get(*name).is_some()) {
+            return false;
+        }
+        self.0.borrow_mut().vars.extend(child.vars.drain());
+        true
+    }
+
+    /// Names visible from this scope with their values, sorted by name.
+    /// A name bound again in an inner scope shows only its inner value.
+    pub fn bindings(&self) -> Vec<(Symbol, Value)> {
+        let mut visible = HashMap::new();
+        let mut env = Some(self.clone());
+
+        while let Some(current) = env {
+            let scope = current.0.borrow();
+            for (name, value) in &scope.vars {
+                visible.entry(*name).or_insert_with(|| value.clone());
+            }
+            env = scope.parent.clone();
+        }
+
+        let mut bindings: Vec<_> = visible.into_iter().collect();
+        bindings.sort_by_key(|(name, _)| name.name());
+        bindings
+    }
+}
+
+/// Why evaluation stopped early: a real error, a `return` unwinding to its call,
+/// or a `break`/`continue` unwinding to its loop.
+enum Unwind {
+    Error(EvalError),
+    Return(Value),
+    Break,
+    Continue
+}
+
+impl From<EvalError> for Unwind {
+    fn from(e: EvalError) -> Self { Unwind::Error(e) }
+}
+
+type EvalResult = Result<Value, Unwind>;
+
+/// Walks the syntax tree, binding each `let` and `fn` in `env` as it goes.
+/// Runaway recursion ends in `EvalError::StackOverflow` once the thread's stack is nearly used up,
+/// so a thread with more stack gets further before that.
+#[derive(Default)]
+pub struct Interpreter {
+    pub env: Environment
+}
+
+impl Interpreter {
+    pub fn new() -> Self { Self::default() }
+
+    /// Runs every statement in order. The result is the value of the last statement,
+    /// `Unit` if that was a declaration.
+    /// Each program runs in a child of `env` and its bindings are only kept if it gets to the end,
+    /// so those of one that fails are gone. They are moved into `env`, unless the program binds
+    /// a name again that closures from earlier programs may see: then the child becomes `env`,
+    /// so those closures keep seeing the old value.
+    pub fn run(&mut self, program: &Program) -> Result<Value, EvalError> {
+        let env = self.env.child();
+
+        let value = finish(self.exec_all(&program.statements, &env))?;
+        if !self.env.absorb(&env) {
+            self.env = env;
+        }
+        Ok(value)
+    }
+
+    fn exec_all(&mut self, statements: &[Stmt], env: &Environment) -> EvalResult {
+        let mut last = Value::Unit;
+
+        for stmt in statements {
+            last = self.exec(stmt, env)?;
+        }
+
+        Ok(last)
+    }
+
+    fn exec(&mut self, stmt: &Stmt, env: &Environment) -> EvalResult {
+        match &stmt.kind {
+            StmtKind::Let { name, value, .. } => {
+                let value = self.eval(value, env)?;
+                env.define(name.kind, value);
+                Ok(Value::Unit)
+            },
+            StmtKind::Assign { target, op, value } => {
+                let undefined = || EvalError::UndefinedName {
+                    name: target.kind.to_string(),
+                    span: target.span
+                };
+
+                let mut value = self.eval(value, env)?;
+                if let Some(op) = op {
+                    let current = env.get(target.kind).ok_or_else(undefined)?;
+                    value = arithmetic(*op, current, value, stmt)?;
+                }
+
+                if !env.assign(target.kind, value) {
+                    return Err(Unwind::Error(undefined()));
+                }
+                Ok(Value::Unit)
+            },
+            StmtKind::Fn(def) => {
+                let name = def.name.as_ref().expect("`fn` statements are always named").kind;
+                env.define(name, closure(def, env));
+                Ok(Value::Unit)
+            },
+            StmtKind::Return(value) => {
+                let value = match value {
+                    Some(value) => self.eval(value, env)?,
+                    None => Value::Unit
+                };
+                Err(Unwind::Return(value))
+            },
+            StmtKind::Break => Err(Unwind::Break),
+            StmtKind::Continue => Err(Unwind::Continue),
+            StmtKind::Expr(expr) => self.eval(expr, env)
+        }
+    }
+
+    fn eval(&mut self, expr: &Expr, env: &Environment) -> EvalResult {
+        if stack::exhausted() {
+            return Err(Unwind::Error(EvalError::StackOverflow { span: expr.span }));
+        }
+
+        match &expr.kind {
+            ExprKind::Int(n) => Ok(Value::Int(*n)),
+            ExprKind::Float(n) => Ok(Value::Float(*n)),
+            ExprKind::Str(s) => Ok(Value::Str(s.clone())),
+            ExprKind::Char(c) => Ok(Value::Char(*c)),
+            ExprKind::Bool(b) => Ok(Value::Bool(*b)),
+            ExprKind::Ident(name) => env.get(*name).ok_or_else(|| Unwind::Error(EvalError::UndefinedName {
+                name: name.to_string(),
+                span: expr.span
+            })),
+            ExprKind::Unary { op, operand } => {
+                let value = self.eval(operand, env)?;
+                Ok(unary(*op, value, expr)?)
+            },
+            ExprKind::Binary { op: op @ (OperatorKind::And | OperatorKind::Or), lhs, rhs } => {
+                let mismatch = |lhs: &Value, rhs: Option<&Value>| EvalError::TypeMismatch {
+                    op:   *op,
+                    lhs:  lhs.type_name(),
+                    rhs:  rhs.map(Value::type_name),
+                    span: expr.span
+                };
+
+                // `false && ...` and `true || ...` are decided without evaluating the right side.
+                let left = self.eval(lhs, env)?;
+                match (op, &left) {
+                    (_, Value::Bool(b)) if *b == (*op == OperatorKind::Or) => return Ok(left),
+                    (_, Value::Bool(_)) => {},
+                    _ => return Err(Unwind::Error(mismatch(&left, None)))
+                }
+
+                match self.eval(rhs, env)? {
+                    right @ Value::Bool(_) => Ok(right),
+                    right => Err(Unwind::Error(mismatch(&left, Some(&right))))
+                }
+            },
+            ExprKind::Binary { op, lhs, rhs } => {
+                let lhs = self.eval(lhs, env)?;
+                let rhs = self.eval(rhs, env)?;
+
+                match op {
+                    OperatorKind::Plus | OperatorKind::Minus | OperatorKind::Mul | OperatorKind::Div =>
+                        Ok(arithmetic(*op, lhs, rhs, expr)?),
+                    OperatorKind::Range => Ok(range(lhs, rhs, expr)?),
+                    _ => Ok(comparison(*op, lhs, rhs, expr)?)
+                }
+            },
+            ExprKind::Call { callee, args } => {
+                let callee_value = self.eval(callee, env)?;
+                let mut values = Vec::with_capacity(args.len());
+                for arg in args {
+                    values.push(self.eval(arg, env)?);
+                }
+
+                Ok(self.call(callee_value, values, expr)?)
+            },
+            ExprKind::Fn(def) => Ok(closure(def, env)),
+            ExprKind::Block(statements) => self.exec_all(statements, &env.child()),
+            ExprKind::If { cond, then, otherwise } => {
+                let value = self.eval(cond, env)?;
+                let branch = if truth(value, cond, "`if` condition")? { Some(then) } else { otherwise.as_ref() };
+
+                match branch {
+                    Some(body) => self.exec_all(body, &env.child()),
+                    None => Ok(Value::Unit)
+                }
+            },
+            ExprKind::While { cond, body } => loop {
+                let value = self.eval(cond, env)?;
+                if !truth(value, cond, "`while` condition")? {
+                    return Ok(Value::Unit);
+                }
+
+                if !self.iteration(body, &env.child())? {
+                    return Ok(Value::Unit);
+                }
+            },
+            ExprKind::For { var, iter, body } => {
+                let items: Box<dyn Iterator<Item = Value>> = match self.eval(iter, env)? {
+                    Value::Range(start, end) => Box::new((start..end).map(Value::Int)),
+                    Value::Str(s) => Box::new(s.chars().collect::<Vec<_>>().into_iter().map(Value::Char)),
+                    other => return Err(Unwind::Error(EvalError::NotIterable {
+                        found: other.type_name(),
+                        span:  iter.span
+                    }))
+                };
+
+                // Every pass gets a fresh scope, so closures made in the body keep their own `var`.
+                for item in items {
+                    let scope = env.child();
+                    scope.define(var.kind, item);
+
+                    if !self.iteration(body, &scope)? {
+                        break;
+                    }
+                }
+
+                Ok(Value::Unit)
+            }
+        }
+    }
+
+    /// Binds `args` to the parameters in a fresh scope on top of the captured one
+    /// and runs the body; the result is the `return`ed value or the last statement's.
+    fn call(&mut self, callee: Value, args: Vec<Value>, expr: &Expr) -> Result<Value, EvalError> {
+        let Value::Function(function) = callee else {
+            return Err(EvalError::NotCallable {
+                found: callee.type_name(),
+                span:  expr.span
+            });
+        };
+
+        if function.def.params.len() != args.len() {
+            return Err(EvalError::ArityMismatch {
+                expected: function.def.params.len(),
+                found:    args.len(),
+                span:     expr.span
+            });
+        }
+
+        let scope = function.env.child();
+        for (param, arg) in function.def.params.iter().zip(args) {
+            scope.define(param.name.kind, arg);
+        }
+
+        finish(self.exec_all(&function.def.body, &scope))
+    }
+
+    /// Runs one pass of a loop body in `scope`, returns whether the loop goes on.
+    fn iteration(&mut self, body: &[Stmt], scope: &Environment) -> Result<bool, Unwind> {
+        match self.exec_all(body, scope) {
+            Ok(_) | Err(Unwind::Continue) => Ok(true),
+            Err(Unwind::Break) => Ok(false),
+            Err(e) => Err(e)
+        }
+    }
+}
+
+/// The value of a function body or program: a `return` ends either one early.
+fn finish(result: EvalResult) -> Result<Value, EvalError> {
+    match result {
+        Ok(value) | Err(Unwind::Return(value)) => Ok(value),
+        Err(Unwind::Error(e)) => Err(e),
+        Err(Unwind::Break | Unwind::Continue) => unreachable!("the parser only allows `break` and `continue` in loops")
+    }
+}
+
+fn closure(def: &Rc<FnDef>, env: &Environment) -> Value {
+    Value::Function(Rc::new(Closure { def: def.clone(), env: env.clone() }))
+}
+
+/// A condition's value, which has to be a `Bool`; `context` names the condition for the error.
+fn truth(value: Value, cond: &Expr, context: &'static str) -> Result<bool, EvalError> {
+    match value {
+        Value::Bool(b) => Ok(b),
+        other => Err(EvalError::NotBool {
+            found: other.type_name(),
+            context,
+            span:  cond.span
+        })
+    }
+}
+
+/// `-` negates numbers (checked for integers), `!` negates bools.
+fn unary(op: OperatorKind, value: Value, expr: &Expr) -> Result<Value, EvalError> {
+    match (op, value) {
+        (OperatorKind::Minus, Value::Int(n)) => n.checked_neg().map(Value::Int).ok_or(EvalError::Overflow {
+            op,
+            span: expr.span
+        }),
+        (OperatorKind::Minus, Value::Float(n)) => Ok(Value::Float(-n)),
+        (OperatorKind::Not, Value::Bool(b)) => Ok(Value::Bool(!b)),
+        (_, other) => Err(EvalError::TypeMismatch {
+            op,
+            lhs:  other.type_name(),
+            rhs:  None,
+            span: expr.span
+        })
+    }
+}
+
+/// `==` and `!=` take any two values of the same type, the orderings only numbers, strings and chars.
+/// Integers and floats compare by value with each other, like in arithmetic.
+fn comparison(op: OperatorKind, lhs: Value, rhs: Value, expr: &Expr) -> Result<Value, EvalError> {
+    let numbers = lhs.as_float().zip(rhs.as_float());
+    let same_type = numbers.is_some() || std::mem::discriminant(&lhs) == std::mem::discriminant(&rhs);
+    let ordered = numbers.is_some() || matches!(lhs, Value::Str(_) | Value::Char(_));
+
+    if !same_type || !(ordered || matches!(op, OperatorKind::EqEq | OperatorKind::NotEq)) {
+        return Err(EvalError::TypeMismatch {
+            op,
+            lhs:  lhs.type_name(),
+            rhs:  Some(rhs.type_name()),
+            span: expr.span
+        });
+    }
+
+    // Integers are compared exactly, only mixed pairs go through `f64`. `None` means NaN was involved.
+    let ordering = match (&lhs, &rhs) {
+        (Value::Int(a), Value::Int(b)) => Some(a.cmp(b)),
+        (Value::Str(a), Value::Str(b)) => Some(a.cmp(b)),
+        (Value::Char(a), Value::Char(b)) => Some(a.cmp(b)),
+        _ => numbers.and_then(|(a, b)| a.partial_cmp(&b))
+    };
+    let equal = ordering.map_or(lhs == rhs, Ordering::is_eq);
+
+    Ok(Value::Bool(match op {
+        OperatorKind::EqEq => equal,
+        OperatorKind::NotEq => !equal,
+        OperatorKind::Lt => ordering.is_some_and(Ordering::is_lt),
+        OperatorKind::LtEq => ordering.is_some_and(Ordering::is_le),
+        OperatorKind::Gt => ordering.is_some_and(Ordering::is_gt),
+        OperatorKind::GtEq => ordering.is_some_and(Ordering::is_ge),
+        _ => unreachable!("only comparison operators reach `comparison`")
+    }))
+}
+
+/// `start..end` of two integers.
+fn range(lhs: Value, rhs: Value, expr: &Expr) -> Result<Value, EvalError> {
+    match (lhs, rhs) {
+        (Value::Int(start), Value::Int(end)) => Ok(Value::Range(start, end)),
+        (lhs, rhs) => Err(EvalError::TypeMismatch {
+            op:   OperatorKind::Range,
+            lhs:  lhs.type_name(),
+            rhs:  Some(rhs.type_name()),
+            span: expr.span
+        })
+    }
+}
+
+/// `int op int` stays an integer (checked), any float operand makes the result a float.
+/// `+` also concatenates strings.
+fn arithmetic<K>(op: OperatorKind, lhs: Value, rhs: Value, expr: &SyntaxNode<K>) -> Result<Value, EvalError> {
+    if let (Value::Str(a), Value::Str(b), OperatorKind::Plus) = (&lhs, &rhs, op) {
+        return Ok(Value::Str(format!("{}{}", a, b)));
+    }
+
+    if let (Value::Int(a), Value::Int(b)) = (&lhs, &rhs) {
+        if op == OperatorKind::Div && *b == 0 {
+            return Err(EvalError::DivisionByZero { span: expr.span });
+        }
+
+        let result = match op {
+            OperatorKind::Plus => a.checked_add(*b),
+            OperatorKind::Minus => a.checked_sub(*b),
+            OperatorKind::Mul => a.checked_mul(*b),
+            OperatorKind::Div => a.checked_div(*b),
+            _ => unreachable!("only arithmetic operators reach `arithmetic`")
+        };
+
+        return result.map(Value::Int).ok_or(EvalError::Overflow {
+            op,
+            span: expr.span
+        });
+    }
+
+    let (Some(a), Some(b)) = (lhs.as_float(), rhs.as_float()) else {
+        return Err(EvalError::TypeMismatch {
+            op,
+            lhs:  lhs.type_name(),
+            rhs:  Some(rhs.type_name()),
+            span: expr.span
+        });
+    };
+
+    Ok(Value::Float(match op {
+        OperatorKind::Plus => a + b,
+        OperatorKind::Minus => a - b,
+        OperatorKind::Mul => a * b,
+        OperatorKind::Div => a / b,
+        _ => unreachable!("only arithmetic operators reach `arithmetic`")
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::span::SourceMap;
+    use crate::testing::{run, run_all, Stage};
+    use crate::{lex, parse};
+
+    fn value(text: &str) -> String { run(text).unwrap_or_else(|e| panic!("errors {:?} in {:?}", e, text)) }
+
+    fn error(text: &str) -> Vec<&'static str> { run(text).unwrap_err() }
+
+    #[test]
+    fn bindings_and_arithmetic() {
+        assert_eq!(value("let a = 2\nlet b be a * 3\nb - 1"), "5");
+        assert_eq!(value("7 / 2"), "3");
+        assert_eq!(value("1 + 0.5"), "1.5");
+        assert_eq!(value("\"a\" + \"b\""), "ab");
+        assert_eq!(value("let a = 1"), "");
+    }
+
+    #[test]
+    fn runtime_errors() {
+        assert_eq!(error("let z = 0\n1 / z"), vec!["R0003"]);
+        assert_eq!(error("9223372036854775807 + 1"), vec!["R0004"]);
+        assert_eq!(error("-(-9223372036854775807 - 1)"), vec!["R0004"]);
+    }
+
+    #[test]
+    fn typed_values() {
+        assert_eq!(value("2.0"), "2.0");
+        assert_eq!(value("'c'"), "c");
+        assert_eq!(value("1 < 2"), "true");
+        assert_eq!(value("2 == 2.0"), "true");
+        assert_eq!(value("1..3"), "1..3");
+        assert_eq!(value("{ }"), "");
+    }
+
+    #[test]
+    fn functions_and_closures() {
+        assert_eq!(value("fn add(a, b) { a + b }\nadd(1, 2)"), "3");
+        assert_eq!(value("fn fact(n) { if n < 2 { return 1 }\nn * fact(n - 1) }\nfact(10)"), "3628800");
+        assert_eq!(value("fn counter() { let mut n = 0\nfn() { n += 1\nn } }\nlet c = counter()\nc()\nc()"), "2");
+        assert_eq!(value("let x = 1\nfn f() { x }\n{ let x = 2\nf() }"), "1");
+        assert_eq!(value("fn f() { }\nf"), "<fn f>");
+    }
+
+    #[test]
+    fn conditionals() {
+        assert_eq!(value("let n = 5\nif n < 3 { \"small\" } else if n < 10 { \"medium\" } else { \"large\" }"), "medium");
+        assert_eq!(value("if false { 1 }"), "");
+        assert_eq!(value("let z = 0\nfalse && 1 / z == 0"), "false");
+        assert_eq!(value("let z = 0\ntrue || 1 / z == 0"), "true");
+        assert_eq!(value("!(1 >= 2) && \"a\" < \"b\" && 'a' != 'b'"), "true");
+    }
+
+    #[test]
+    fn loops() {
+        assert_eq!(value("let mut sum = 0\nfor i in 0..5 { sum += i }\nsum"), "10");
+        assert_eq!(value("let mut s = \"\"\nfor c in \"abc\" { if c == 'b' { continue }\ns = s + \"x\" }\ns"), "xx");
+        assert_eq!(value("let mut i = 0\nwhile true { i += 1\nif i == 3 { break } }\ni"), "3");
+        assert_eq!(value("for i in 5..0 { }"), "");
+        // Every pass binds the loop variable afresh.
+        assert_eq!(value("let mut f = fn() { 0 }\nfor i in 0..3 { if i == 1 { f = fn() { i } } }\nf()"), "1");
+    }
+
+    #[test]
+    fn shadowing() {
+        assert_eq!(value("let x = 1\n{ let x = \"s\"\nx }"), "s");
+        assert_eq!(value("let x = 1\n{ let x = x + 1 }\nx"), "1");
+        // A program that binds a name again leaves what earlier closures captured alone.
+        assert_eq!(run_all(&["let x = 1", "fn f() { x }", "let x = \"s\"", "f()"], Stage::Run).pop().unwrap(), Ok("1".to_string()));
+        assert_eq!(run_all(&["let x = 1", "let g = { let y = 2\nfn() { x + y } }", "let x = 5", "g()"], Stage::Run).pop().unwrap(),
+                   Ok("3".to_string()));
+    }
+
+    #[test]
+    fn programs_share_a_scope() {
+        let mut sources = SourceMap::new();
+        let mut interpreter = Interpreter::new();
+        let mut depths = Vec::new();
+
+        for text in ["let x = 1", "fn f() { x }", "let y = f() + 1", "let x = 2", "let z = x + y", "let w = 1 / 0"] {
+            let file_id = sources.add("test", text);
+            let (program, _) = parse(lex(sources.get(file_id), false).unwrap());
+            let _ = interpreter.run(&program);
+
+            let (mut depth, mut env) = (0, Some(interpreter.env.clone()));
+            while let Some(current) = env {
+                depth+=1;
+                env = current.0.borrow().parent.clone();
+            }
+            depths.push(depth);
+        }
+
+        // Only binding `x` again, which `f` sees, takes a scope of its own.
+        assert_eq!(depths, vec![1, 1, 1, 2, 2, 2]);
+        assert_eq!(interpreter.env.bindings().iter().map(|(name, _)| name.to_string()).collect::<Vec<_>>(), vec!["f", "x", "y", "z"]);
+    }
+
+    #[test]
+    fn assignment() {
+        assert_eq!(value("let mut x = 1\nx += 2\nx *= 3\nx"), "9");
+        assert_eq!(value("let mut x = 1\n{ x = 5 }\nx"), "5");
+        assert_eq!(value("let mut s = \"a\"\nfn add() { s += \"b\" }\nadd()\nadd()\ns"), "abb");
+    }
+
+    #[test]
+    fn runaway_recursion_is_an_error() {
+        let recursion = "fn f(n) { f(n + 1) + 1 }\nf(0)";
+        assert_eq!(error(recursion), vec!["R0007"]);
+
+        // On a thread half the size the standard library starts them with, too.
+        let small = std::thread::Builder::new().stack_size(1 << 20).spawn(move || error(recursion));
+        assert_eq!(small.unwrap().join().unwrap(), vec!["R0007"]);
+    }
+}
@@ -0,0 +1,648 @@
+use std::borrow::Cow;
+
+use unicode_normalization::{is_nfc_quick, IsNormalized, UnicodeNormalization};
+use unicode_xid::UnicodeXID;
+
+use crate::error::LexError;
+use crate::span::{SourceFile, Span};
+use crate::{Token, TokenKind};
+
+pub static KEYWORDS: [&str; 14] = [ "let", "mut", "be", "fn", "return", "if", "else", "true", "false",
+                                   "while", "for", "in", "break", "continue"];
+pub static OPERATORS: [&str; 26] = ["=", "+", "-", "*", "/", "(", ")", "{", "}", ",",
+                                    "==", "!=", "<", "<=", ">", ">=", "&&", "||", "!", "..",
+                                    "+=", "-=", "*=", "/=", ":", "->"];
+
+/// Type suffixes a numeric literal may end with, e.g. `255u8` or `1e-3f32`.
+pub static INT_SUFFIXES: [&str; 8] = ["i8", "i16", "i32", "i64", "u8", "u16", "u32", "u64"];
+pub static FLOAT_SUFFIXES: [&str; 2] = ["f32", "f64"];
+
+/// Identifiers follow Unicode's XID rules, with `_` allowed as a first character too.
+fn is_ident_start(ch: char) -> bool { ch.is_xid_start() || ch == '_' }
+
+fn is_ident_continue(ch: char) -> bool { ch.is_xid_continue() }
+
+fn determine_kind(token: &str) -> TokenKind {
+    for k in KEYWORDS {
+        if token.eq(k) {
+            return TokenKind::Keyword
+        }
+    }
+
+    TokenKind::Word
+}
+
+/// Splits a literal the lexer accepted into its digits and its type suffix (possibly empty).
+pub fn split_suffix(text: &str) -> (&str, &str) {
+    let is_radix = text.len() > 1 && matches!(&text[..2], "0x" | "0o" | "0b");
+
+    INT_SUFFIXES.iter()
+        .chain(if is_radix { [].iter() } else { FLOAT_SUFFIXES.iter() })
+        .find(|s| text.len() > s.len() && text.ends_with(*s))
+        .map_or((text, ""), |s| text.split_at(text.len() - s.len()))
+}
+
+/// Walks the source one character at a time and cuts it into tokens.
+/// Whitespace of any kind only separates tokens, so `a=1` and `a = 1` lex the same.
+/// Only byte offsets are tracked while scanning, rows and columns come from the `SourceFile`
+/// when a diagnostic or `Token::describe` asks for them. `last_end` is where the last token
+/// that was not a comment ended, for `Token::newline_before`.
+/// With `keep_comments`, comments are collected in `pending` until the next token takes them as trivia.
+///
+/// Tokens are produced lazily by iterating. Iteration ends at the first malformed token,
+/// after which `error` says what was wrong; the parser can consume a `Lexer` directly.
+pub struct Lexer<'a> {
+    source:        &'a SourceFile,
+    src:           &'a str,
+    offset:        usize,
+    last_end:      usize,
+    keep_comments: bool,
+    pending:       Vec<Token<'a>>,
+    error:         Option<LexError>
+}
+
+impl<'a> Lexer<'a> {
+    pub fn new(source: &'a SourceFile, keep_comments: bool) -> Self {
+        Self { source, src: &source.text, offset: 0, last_end: 0, keep_comments, pending: Vec::new(), error: None }
+    }
+
+    /// Why iteration stopped early, if it did.
+    pub fn error(&self) -> Option<&LexError> { self.error.as_ref() }
+
+    fn peek(&self) -> Option<char> { self.src[self.offset..].chars().next() }
+
+    fn peek_nth(&self, n: usize) -> Option<char> { self.src[self.offset..].chars().nth(n) }
+
+    fn bump(&mut self) -> Option<char> {
+        let ch = self.peek()?;
+        self.offset += ch.len_utf8();
+        Some(ch)
+    }
+
+    fn eat_while(&mut self, pred: impl Fn(char) -> bool) {
+        while let Some(ch) = self.peek() {
+            if !pred(ch) {
+                break;
+            }
+            self.bump();
+        }
+    }
+
+    fn span_from(&self, start: usize) -> Span { Span::make(self.source.id, start, self.offset) }
+
+    /// Longest entry of `OPERATORS` the remaining input starts with.
+    fn match_operator(&self) -> Option<&'static str> {
+        let rest = &self.src[self.offset..];
+        OPERATORS.iter()
+            .filter(|o| rest.starts_with(*o))
+            .max_by_key(|o| o.len())
+            .copied()
+    }
+
+    fn make_token(&self, start: usize, kind: TokenKind) -> Token<'a> {
+        let span = self.span_from(start);
+        let newline_before = self.src[self.last_end..start].contains(['\n', '\r']);
+
+        Token::make(&self.src[span.range()], span, kind, newline_before)
+    }
+
+    /// Skips whitespace and comments up to the next token.
+    fn skip_trivia(&mut self) -> Result<(), LexError> {
+        loop {
+            self.eat_while(char::is_whitespace);
+
+            let start = self.offset;
+            let rest = &self.src[self.offset..];
+
+            if rest.starts_with("//") {
+                self.eat_while(|c| c != '\n' && c != '\r');
+            } else if rest.starts_with("/*") {
+                self.block_comment()?;
+            } else {
+                return Ok(());
+            }
+
+            if self.keep_comments {
+                let comment = self.make_token(start, TokenKind::Comment);
+                self.pending.push(comment);
+            }
+        }
+    }
+
+    /// `/* ... */`, where every `/*` inside needs its own `*/`.
+    fn block_comment(&mut self) -> Result<(), LexError> {
+        let start = self.offset;
+        let mut depth = 0;
+
+        loop {
+            let rest = &self.src[self.offset..];
+
+            if rest.starts_with("/*") {
+                depth+=1;
+                self.offset += 2;
+            } else if rest.starts_with("*/") {
+                depth-=1;
+                self.offset += 2;
+                if depth == 0 {
+                    return Ok(());
+                }
+            } else if self.bump().is_none() {
+                return Err(LexError::Unterminated {
+                    what: "block comment",
+                    span: Span::make(self.source.id, start, start + 2)
+                });
+            }
+        }
+    }
+
+    /// The next token, or once the input is exhausted any comments left after the last one.
+    fn next_token(&mut self) -> Result<Option<Token<'a>>, LexError> {
+        self.skip_trivia()?;
+
+        let start = self.offset;
+        let Some(ch) = self.peek() else {
+            return Ok((!self.pending.is_empty()).then(|| self.pending.remove(0)));
+        };
+
+        // `.5` is a number, but not right after one: `1u8.5` is not `1u8` followed by `.5`.
+        let leading_dot = ch == '.' && self.peek_nth(1).is_some_and(|c| c.is_ascii_digit())
+            && !self.src[..start].chars().next_back().is_some_and(is_ident_continue);
+
+        let kind = if ch.is_ascii_digit() || leading_dot {
+            self.number()?;
+            TokenKind::Numeric
+        } else if ch == '"' {
+            self.string()?;
+            TokenKind::String
+        } else if ch == 'r' && self.src[self.offset+1..].trim_start_matches('#').starts_with('"') {
+            self.raw_string()?;
+            TokenKind::String
+        } else if ch == '\'' {
+            self.char()?;
+            TokenKind::Char
+        } else if is_ident_start(ch) {
+            self.eat_while(is_ident_continue);
+            determine_kind(&self.src[start..self.offset])
+        } else if let Some(op) = self.match_operator() {
+            self.offset += op.len();
+            TokenKind::Operator
+        } else {
+            let span = Span::make(self.source.id, start, start + ch.len_utf8());
+            return Err(LexError::UnexpectedChar { ch, span });
+        };
+
+        let mut token = self.make_token(start, kind);
+        token.trivia = std::mem::take(&mut self.pending);
+        self.last_end = self.offset;
+
+        Ok(Some(token))
+    }
+
+    /// `0x`/`0o`/`0b` integers, or decimals with an optional fraction and exponent,
+    /// then an optional type suffix. `_` may separate digits anywhere after the first one.
+    fn number(&mut self) -> Result<(), LexError> {
+        let start = self.offset;
+
+        let radix = match (self.peek(), self.peek_nth(1)) {
+            (Some('0'), Some('x')) => 16,
+            (Some('0'), Some('o')) => 8,
+            (Some('0'), Some('b')) => 2,
+            _ => 10
+        };
+
+        let mut is_float = false;
+        let mut has_exponent = false;
+
+        if radix == 10 {
+            self.digits(10);
+
+            // `1..2` is a range, not `1.` followed by `.2`
+            if self.peek() == Some('.') && self.peek_nth(1).is_some_and(|c| c.is_ascii_digit()) {
+                self.bump();
+                self.digits(10);
+                is_float = true;
+            }
+
+            if matches!(self.peek(), Some('e' | 'E')) {
+                let exponent = self.offset;
+                self.bump();
+                if matches!(self.peek(), Some('+' | '-')) {
+                    self.bump();
+                }
+                if self.digits(10) == 0 {
+                    return Err(LexError::MissingDigits {
+                        what: "exponent",
+                        span: self.span_from(exponent)
+                    });
+                }
+                is_float = true;
+                has_exponent = true;
+            }
+        } else {
+            self.offset += 2;
+            if self.digits(radix) == 0 {
+                // Report the offending character rather than the empty literal when there is one.
+                if let Some(ch) = self.peek().filter(|c| c.is_alphanumeric()) {
+                    return Err(self.invalid_digit(ch, radix));
+                }
+                return Err(LexError::MissingDigits {
+                    what: radix_name(radix),
+                    span: self.span_from(start)
+                });
+            }
+        }
+
+        // A second fraction, which would otherwise lex as a number of its own, like the `.5` in `1.5.5`.
+        if self.peek() == Some('.') && self.peek_nth(1).is_some_and(|c| c.is_ascii_digit()) {
+            let why = if radix != 10 {
+                "only decimal literals can have a fraction"
+            } else if has_exponent {
+                "the fraction has to come before the exponent"
+            } else {
+                "the literal already has a fraction"
+            };
+
+            let dot = self.offset;
+            self.bump();
+            self.digits(10);
+            return Err(LexError::UnexpectedFraction { why, span: self.span_from(dot) });
+        }
+
+        // A digit the radix does not allow, like the `2` in `0b102`.
+        if let Some(ch) = self.peek().filter(|c| c.is_ascii_digit()) {
+            return Err(self.invalid_digit(ch, radix));
+        }
+
+        let suffix_start = self.offset;
+        self.eat_while(is_ident_continue);
+        let suffix = &self.src[suffix_start..self.offset];
+
+        let valid = suffix.is_empty()
+            || FLOAT_SUFFIXES.contains(&suffix) && radix == 10
+            || INT_SUFFIXES.contains(&suffix) && !is_float;
+
+        if !valid {
+            // `0xfZ` or `0b1f` has a bad digit rather than a bad suffix; suffixes start with `i`/`u`/`f`.
+            let first = suffix.chars().next().unwrap();
+            if radix != 10 && !matches!(first, 'i' | 'u') {
+                self.offset = suffix_start;
+                return Err(self.invalid_digit(first, radix));
+            }
+
+            return Err(LexError::InvalidSuffix {
+                suffix: suffix.to_string(),
+                float:  is_float,
+                span:   self.span_from(suffix_start)
+            });
+        }
+
+        Ok(())
+    }
+
+    /// `"..."`, which may span lines and contain escapes.
+    fn string(&mut self) -> Result<(), LexError> {
+        let start = self.offset;
+        self.bump();
+
+        loop {
+            match self.bump() {
+                Some('"') => return Ok(()),
+                Some('\\') => self.escape()?,
+                Some(_) => {},
+                None => return Err(LexError::Unterminated {
+                    what: "string literal",
+                    span: Span::make(self.source.id, start, start + 1)
+                })
+            }
+        }
+    }
+
+    /// `r"..."` or `r#"..."#` with any number of `#`. Nothing inside is an escape,
+    /// and the string only ends at a `"` followed by as many `#` as it started with.
+    fn raw_string(&mut self) -> Result<(), LexError> {
+        let start = self.offset;
+        self.bump();
+
+        let hashes = self.src[self.offset..].len() - self.src[self.offset..].trim_start_matches('#').len();
+        self.offset += hashes + 1;
+
+        let terminator = format!("\"{}", "#".repeat(hashes));
+        match self.src[self.offset..].find(&terminator) {
+            Some(end) => {
+                self.offset += end + terminator.len();
+                Ok(())
+            },
+            None => Err(LexError::Unterminated {
+                what: "raw string literal",
+                span: Span::make(self.source.id, start, self.offset)
+            })
+        }
+    }
+
+    /// `'x'`, where `x` is exactly one character or escape.
+    fn char(&mut self) -> Result<(), LexError> {
+        let start = self.offset;
+        self.bump();
+
+        let mut count = 0;
+        loop {
+            match self.peek() {
+                Some('\'') => break,
+                Some('\\') => {
+                    self.bump();
+                    self.escape()?;
+                },
+                // A lone `'` without its partner on the same line.
+                Some('\n' | '\r') | None => return Err(LexError::Unterminated {
+                    what: "character literal",
+                    span: Span::make(self.source.id, start, start + 1)
+                }),
+                Some(_) => {
+                    self.bump();
+                }
+            }
+            count+=1;
+        }
+        self.bump();
+
+        if count != 1 {
+            return Err(LexError::CharLength { span: self.span_from(start) });
+        }
+
+        Ok(())
+    }
+
+    /// Validates the escape whose `\\` was just consumed.
+    fn escape(&mut self) -> Result<(), LexError> {
+        let start = self.offset - 1;
+
+        let valid = match self.bump() {
+            Some('n' | 't' | 'r' | '0' | '\\' | '"' | '\'') => true,
+            // A `\\` at the end of a line continues the string on the next one, minus indentation.
+            Some('\n' | '\r') => {
+                self.eat_while(char::is_whitespace);
+                true
+            },
+            Some('u') => {
+                let digits = self.src[self.offset..].strip_prefix('{')
+                    .and_then(|rest| rest.split_once('}'))
+                    .map(|(digits, _)| digits)
+                    .filter(|d| (1..=6).contains(&d.len()) && d.chars().all(|c| c.is_ascii_hexdigit()));
+
+                match digits.and_then(|d| u32::from_str_radix(d, 16).ok()).and_then(char::from_u32) {
+                    Some(_) => {
+                        self.offset += digits.unwrap().len() + 2;
+                        true
+                    },
+                    None => {
+                        self.eat_while(|c| c == '{' || c.is_ascii_hexdigit());
+                        if self.peek() == Some('}') {
+                            self.bump();
+                        }
+                        false
+                    }
+                }
+            },
+            Some(_) => false,
+            None => return Ok(())
+        };
+
+        if !valid {
+            return Err(LexError::InvalidEscape {
+                escape: self.src[start..self.offset].to_string(),
+                span:   self.span_from(start)
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Eats digits of `radix` and `_` separators, returning how many digits there were.
+    fn digits(&mut self, radix: u32) -> usize {
+        let mut count = 0;
+
+        while let Some(ch) = self.peek() {
+            if ch.is_digit(radix) {
+                count+=1;
+            } else if ch != '_' || count == 0 {
+                break;
+            }
+            self.bump();
+        }
+
+        count
+    }
+
+    fn invalid_digit(&self, ch: char, radix: u32) -> LexError {
+        let span = Span::make(self.source.id, self.offset, self.offset + ch.len_utf8());
+        LexError::InvalidDigit { ch, radix: radix_name(radix), span }
+    }
+}
+
+impl<'a> Iterator for Lexer<'a> {
+    type Item = Token<'a>;
+
+    fn next(&mut self) -> Option<Token<'a>> {
+        if self.error.is_some() {
+            return None;
+        }
+
+        self.next_token().unwrap_or_else(|e| {
+            self.error = Some(e);
+            None
+        })
+    }
+}
+
+fn radix_name(radix: u32) -> &'static str {
+    match radix {
+        2 => "binary literal",
+        8 => "octal literal",
+        16 => "hexadecimal literal",
+        _ => "decimal literal"
+    }
+}
+
+/// The name a `Word` token's text stands for. `é` may be written precomposed or as `e`
+/// plus an accent, both must name the same thing, so names are compared in NFC.
+/// Most names already are, those are returned as they are.
+pub fn normalize(name: &str) -> Cow<'_, str> {
+    match is_nfc_quick(name.chars()) {
+        IsNormalized::Yes => Cow::Borrowed(name),
+        _ => Cow::Owned(name.nfc().collect())
+    }
+}
+
+/// Contents of a `String` or `Char` token's text with quotes removed and escapes resolved.
+/// The lexer has already rejected malformed escapes.
+pub fn unescape(text: &str) -> String {
+    if let Some(raw) = text.strip_prefix('r') {
+        let hashes = raw.len() - raw.trim_start_matches('#').len();
+        return raw[hashes+1..raw.len()-hashes-1].to_string();
+    }
+
+    let mut out = String::new();
+    let mut chars = text[1..text.len()-1].chars().peekable();
+
+    while let Some(ch) = chars.next() {
+        if ch != '\\' {
+            out.push(ch);
+            continue;
+        }
+
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some('r') => out.push('\r'),
+            Some('0') => out.push('\0'),
+            Some('\n' | '\r') => {
+                while chars.next_if(|c| c.is_whitespace()).is_some() {}
+            },
+            Some('u') => {
+                let digits: String = chars.by_ref().skip(1).take_while(|c| *c != '}').collect();
+                out.extend(u32::from_str_radix(&digits, 16).ok().and_then(char::from_u32));
+            },
+            Some(other) => out.push(other),
+            None => {}
+        }
+    }
+
+    out
+}
+
+/// Tokens of `source`. Comments are dropped unless `keep_comments` is set, in which case
+/// each token carries the comments before it as `trivia`, and comments after the last token
+/// come at the end as `Comment` tokens of their own.
+pub fn lex(source: &SourceFile, keep_comments: bool) -> Result<Vec<Token<'_>>, LexError> {
+    let mut lexer = Lexer::new(source, keep_comments);
+    let tokens = lexer.by_ref().collect();
+
+    match lexer.error {
+        Some(e) => Err(e),
+        None => Ok(tokens)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::span::SourceMap;
+
+    /// Value and kind of every token of `text`, comments dropped.
+    fn tokens(text: &str) -> Vec<(String, TokenKind)> {
+        let mut sources = SourceMap::new();
+        let file_id = sources.add("test", text);
+
+        lex(sources.get(file_id), false).unwrap().into_iter()
+            .map(|token| (token.value.to_string(), token.kind))
+            .collect()
+    }
+
+    fn error(text: &str) -> &'static str {
+        let mut sources = SourceMap::new();
+        let file_id = sources.add("test", text);
+
+        lex(sources.get(file_id), false).unwrap_err().code()
+    }
+
+    #[test]
+    fn kinds() {
+        assert_eq!(tokens("let x be y1+=2"), vec![
+            ("let".to_string(), TokenKind::Keyword),
+            ("x".to_string(), TokenKind::Word),
+            ("be".to_string(), TokenKind::Keyword),
+            ("y1".to_string(), TokenKind::Word),
+            ("+=".to_string(), TokenKind::Operator),
+            ("2".to_string(), TokenKind::Numeric)
+        ]);
+        assert_eq!(error("a @ b"), "L0001");
+    }
+
+    #[test]
+    fn spans_and_line_breaks() {
+        let mut sources = SourceMap::new();
+        let file_id = sources.add("test", "a +\n  bc");
+        let tokens = lex(sources.get(file_id), false).unwrap();
+
+        let spans: Vec<_> = tokens.iter().map(|t| (t.span.start, t.span.end)).collect();
+        assert_eq!(spans, vec![(0, 1), (2, 3), (6, 8)]);
+
+        let breaks: Vec<_> = tokens.iter().map(|t| t.newline_before).collect();
+        assert_eq!(breaks, vec![false, false, true]);
+    }
+
+    #[test]
+    fn unicode_identifiers() {
+        assert_eq!(tokens("größe _x1 名前"), vec![
+            ("größe".to_string(), TokenKind::Word),
+            ("_x1".to_string(), TokenKind::Word),
+            ("名前".to_string(), TokenKind::Word)
+        ]);
+        assert_eq!(normalize("e\u{301}"), "\u{e9}");
+        assert!(matches!(normalize("abc"), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn numbers() {
+        let values = |text: &str| tokens(text).into_iter().map(|(value, _)| value).collect::<Vec<_>>();
+
+        assert_eq!(values("0x1F 0o17 0b1_0 1_000 2.5 .5 1e3 1.5E-3 255u8 1f32 1..2"),
+                   vec!["0x1F", "0o17", "0b1_0", "1_000", "2.5", ".5", "1e3", "1.5E-3", "255u8", "1f32", "1", "..", "2"]);
+        assert_eq!(split_suffix("255u8"), ("255", "u8"));
+        assert_eq!(split_suffix("0x1f"), ("0x1f", ""));
+        assert_eq!(error("0b12"), "L0002");
+        assert_eq!(error("0xfz"), "L0002");
+        assert_eq!(error("0x"), "L0003");
+        assert_eq!(error("1e"), "L0003");
+        assert_eq!(error("1.5q"), "L0004");
+        assert_eq!(error("1.5u8"), "L0004");
+        assert_eq!(error("0x1.5"), "L0008");
+        assert_eq!(error("0b1.1"), "L0008");
+        assert_eq!(error("1.5.5"), "L0008");
+        assert_eq!(error("1e3.5"), "L0008");
+        assert_eq!(error("1u8.5"), "L0001");
+        assert_eq!(values("0x1..2 a .5"), vec!["0x1", "..", "2", "a", ".5"]);
+    }
+
+    #[test]
+    fn strings_and_chars() {
+        assert_eq!(tokens("'c' \"s\" r#\"a\"b\"#"), vec![
+            ("'c'".to_string(), TokenKind::Char),
+            ("\"s\"".to_string(), TokenKind::String),
+            ("r#\"a\"b\"#".to_string(), TokenKind::String)
+        ]);
+        assert_eq!(error("\"abc"), "L0005");
+        assert_eq!(error("r#\"abc\""), "L0005");
+        assert_eq!(error("\"\\q\""), "L0006");
+        assert_eq!(error("'\\u{110000}'"), "L0006");
+        assert_eq!(error("'ab'"), "L0007");
+        assert_eq!(error("''"), "L0007");
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!(unescape(r#""a\tb\n\\\"\0""#), "a\tb\n\\\"\0");
+        assert_eq!(unescape(r#""\u{e9}\u{1F600}""#), "é😀");
+        assert_eq!(unescape("\"one \\\n    two\""), "one two");
+        assert_eq!(unescape(r#"'\''"#), "'");
+        assert_eq!(unescape(r##"r#"a\n"b"#"##), "a\\n\"b");
+    }
+
+    #[test]
+    fn comments() {
+        assert_eq!(tokens("a // one\n/* two /* nested */ */ b"), vec![
+            ("a".to_string(), TokenKind::Word),
+            ("b".to_string(), TokenKind::Word)
+        ]);
+        assert_eq!(error("/* open"), "L0005");
+
+        let mut sources = SourceMap::new();
+        let file_id = sources.add("test", "// one\na // two");
+        let tokens = lex(sources.get(file_id), true).unwrap();
+
+        assert_eq!(tokens.len(), 2);
+        assert_eq!(tokens[0].trivia.len(), 1);
+        assert_eq!(tokens[0].trivia[0].value, "// one");
+        assert_eq!(tokens[1].kind, TokenKind::Comment);
+    }
+}
@@ -0,0 +1,214 @@
+//! Lexer, parser and tree-walking interpreter for the language.
+//! Source text goes through `lex`, then `parse`, `Resolver::resolve`, `Checker::check` and `Interpreter::run`;
+//! every stage reports errors that render to a `Diagnostic` against a `SourceMap`.
+
+pub mod ast;
+pub mod checker;
+pub mod diagnostic;
+pub mod error;
+pub mod eval;
+pub mod lexer;
+pub mod parser;
+pub mod resolver;
+pub mod span;
+mod stack;
+pub mod symbol;
+pub mod value;
+
+#[cfg(test)]
+mod testing;
+
+use std::fmt::{Debug, Display, Formatter};
+use std::rc::Rc;
+
+use crate::span::{SourceFile, Span};
+
+pub use crate::checker::Checker;
+pub use crate::eval::Interpreter;
+pub use crate::lexer::{lex, Lexer};
+pub use crate::parser::parse;
+pub use crate::resolver::Resolver;
+
+/// `col` counts grapheme clusters, i.e. what a terminal shows as one character.
+/// `byte_col` and `utf16_col` are the same column in UTF-8 bytes and UTF-16 units, for editors.
+#[derive(Clone)]
+pub struct Pos {
+    pub file:      Rc<str>,
+    pub row:       usize,
+    pub col:       usize,
+    pub byte_col:  usize,
+    pub utf16_col: usize
+}
+
+impl Pos {
+    pub fn make(file: Rc<str>, row: usize, col: usize, byte_col: usize, utf16_col: usize) -> Self {
+        Self { file, row, col, byte_col, utf16_col }
+    }
+}
+
+impl Debug for Pos {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}:{} (byte {}, utf-16 {})", self.file, self.row, self.col, self.byte_col, self.utf16_col)
+    }
+}
+
+impl Display for Pos {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.row, self.col)
+    }
+}
+
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialOrd, PartialEq)]
+pub enum TokenKind {
+    Word,
+    Keyword,
+    Operator,
+    Numeric,
+    String,
+    Char,
+    Comment
+}
+
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq)]
+pub enum OperatorKind {
+    Eq,
+    Plus,
+    Minus,
+    Mul,
+    Div,
+    EqEq,
+    NotEq,
+    Lt,
+    LtEq,
+    Gt,
+    GtEq,
+    And,
+    Or,
+    Not,
+    Range,
+    PlusEq,
+    MinusEq,
+    MulEq,
+    DivEq
+}
+
+impl OperatorKind {
+    pub fn from_value(value: &str) -> Option<Self> {
+        match value {
+            "=" => Some(OperatorKind::Eq),
+            "+" => Some(OperatorKind::Plus),
+            "-" => Some(OperatorKind::Minus),
+            "*" => Some(OperatorKind::Mul),
+            "/" => Some(OperatorKind::Div),
+            "==" => Some(OperatorKind::EqEq),
+            "!=" => Some(OperatorKind::NotEq),
+            "<" => Some(OperatorKind::Lt),
+            "<=" => Some(OperatorKind::LtEq),
+            ">" => Some(OperatorKind::Gt),
+            ">=" => Some(OperatorKind::GtEq),
+            "&&" => Some(OperatorKind::And),
+            "||" => Some(OperatorKind::Or),
+            "!" => Some(OperatorKind::Not),
+            ".." => Some(OperatorKind::Range),
+            "+=" => Some(OperatorKind::PlusEq),
+            "-=" => Some(OperatorKind::MinusEq),
+            "*=" => Some(OperatorKind::MulEq),
+            "/=" => Some(OperatorKind::DivEq),
+            _ => None
+        }
+    }
+
+    /// The arithmetic a compound assignment applies, `+` for `+=`.
+    pub fn compound(self) -> Option<Self> {
+        match self {
+            OperatorKind::PlusEq => Some(OperatorKind::Plus),
+            OperatorKind::MinusEq => Some(OperatorKind::Minus),
+            OperatorKind::MulEq => Some(OperatorKind::Mul),
+            OperatorKind::DivEq => Some(OperatorKind::Div),
+            _ => None
+        }
+    }
+}
+
+impl Display for OperatorKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let symbol = match self {
+            OperatorKind::Eq => "=",
+            OperatorKind::Plus => "+",
+            OperatorKind::Minus => "-",
+            OperatorKind::Mul => "*",
+            OperatorKind::Div => "/",
+            OperatorKind::EqEq => "==",
+            OperatorKind::NotEq => "!=",
+            OperatorKind::Lt => "<",
+            OperatorKind::LtEq => "<=",
+            OperatorKind::Gt => ">",
+            OperatorKind::GtEq => ">=",
+            OperatorKind::And => "&&",
+            OperatorKind::Or => "||",
+            OperatorKind::Not => "!",
+            OperatorKind::Range => "..",
+            OperatorKind::PlusEq => "+=",
+            OperatorKind::MinusEq => "-=",
+            OperatorKind::MulEq => "*=",
+            OperatorKind::DivEq => "/="
+        };
+        write!(f, "{}", symbol)
+    }
+}
+
+/// `span` is where the token is, as byte offsets into its source file; rows and columns
+/// are only worked out from it when something is shown to a person, see `Token::describe`.
+/// `newline_before` is set when a line break separates the token from the previous one, comments aside.
+/// `trivia` holds the comments right before the token, when the lexer was asked to keep them.
+/// `value` borrows the token's text from the source, so making a token copies no text.
+#[derive(Clone)]
+pub struct Token<'a> {
+    pub value:          &'a str,
+    pub span:           Span,
+    pub kind:           TokenKind,
+    pub newline_before: bool,
+    pub trivia:         Vec<Token<'a>>
+}
+
+impl<'a> Token<'a> {
+    pub fn make(value: &'a str, span: Span, kind: TokenKind, newline_before: bool) -> Self {
+        Self {
+            value,
+            span,
+            kind,
+            newline_before,
+            trivia: Vec::new()
+        }
+    }
+
+    /// The token and its trivia one per line, with the rows and columns they span in `source`.
+    pub fn describe(&self, source: &SourceFile) -> String {
+        let mut out = String::new();
+
+        for comment in &self.trivia {
+            out.push_str(&comment.describe(source));
+            out.push('\n');
+        }
+
+        let (start, end) = (source.pos(self.span.start), source.pos(self.span.end));
+        out.push_str(&format!("[{}-{}:{} @{}..{}]: {} ({:?})",
+                              start, end.row, end.col,
+                              self.span.start, self.span.end, self.value, self.kind));
+        out
+    }
+}
+
+impl Debug for Token<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for comment in &self.trivia {
+            writeln!(f, "{:?}", comment)?;
+        }
+        write!(f, "[@{}..{}]: {} ({:?})", self.span.start, self.span.end, self.value, self.kind)
+    }
+}
+
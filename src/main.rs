@@ -1,257 +1,220 @@
-extern crate core;
-
-use std::collections::HashMap;
-use std::env::var;
-use std::fmt::{Debug, Display, Formatter};
-use std::io::ErrorKind;
-use std::ops::Index;
-use crate::TokenKind::Word;
-
-#[derive(Clone)]
-struct Pos {
-    file: String,
-    row: u16,
-    col: u16
-}
+mod repl;
 
-impl Pos {
-    pub fn new() -> Self { Self { file: String::new(), row: 0, col: 0 } }
+use std::io::{IsTerminal, Read};
 
-    pub fn make(file: String, row: u16, col: u16) -> Self { Self { file, row, col } }
+use lexing::span::SourceMap;
+use lexing::value::Value;
+use lexing::{parse, Checker, Interpreter, Lexer, Resolver};
 
-    pub fn from_tuple(src: (String, u16, u16)) -> Self { Self { file: src.0, row: src.1, col: src.2 } }
-}
+use crate::repl::Repl;
 
-impl Debug for Pos {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}:{}:{}", self.file, self.row, self.col)
-    }
-}
+const USAGE: &str = "\
+usage: lexing [--tokens | --ast] [<file> | - | -e <source>]
+       lexing [-i]
 
-impl Display for Pos {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}:{}:{}", self.file, self.row, self.col)
-    }
+Runs <file>, or standard input when it is `-` or missing, and prints the value of the last statement.
+Without any input and with a terminal on standard input, starts an interactive session instead.
+
+options:
+  -e <source>  run <source> instead of reading a file
+  -i, --repl   start an interactive session
+  --tokens     print the token stream instead of running
+  --ast        print the syntax tree instead of running
+  -h, --help   print this message";
+
+/// The source failed to lex, parse or run.
+const EXIT_ERROR: i32 = 1;
+/// Bad arguments or unreadable input.
+const EXIT_USAGE: i32 = 2;
+
+/// Stack of the thread everything runs on, far more than a main thread gets, so deep
+/// recursion goes a long way before the interpreter reports running out.
+const STACK_SIZE: usize = 512 << 20;
+
+/// How far to take the source before printing.
+enum Mode {
+    Run,
+    Tokens,
+    Ast
 }
 
-#[derive(Debug)]
-#[derive(Clone)]
-#[derive(PartialOrd, PartialEq)]
-enum TokenKind {
-    Word,
-    Keyword,
-    Operator,
-    Numeric
+enum Input {
+    File(String),
+    Stdin,
+    Expr(String),
+    Repl
 }
 
-#[derive(Debug)]
-#[derive(Clone)]
-enum OperatorKind {
-    Eq,
-    Plus,
-    Minus,
-    Mul,
-    Div
+struct Options {
+    mode:  Mode,
+    input: Input
 }
 
-#[derive(Clone)]
-struct Token {
-    value:    String,
-    position: Pos,
-    kind:     TokenKind
+/// ANSI colours only when stderr is a terminal and `NO_COLOR` is not set.
+fn use_colour() -> bool {
+    std::io::stderr().is_terminal() && std::env::var_os("NO_COLOR").is_none()
 }
 
-impl Token {
-    pub fn new() -> Self {
-        Self { value: String::from("empty_token"), position: Pos::new(), kind: TokenKind::Word }
-    }
+/// `None` when help was asked for.
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Option<Options>, String> {
+    let mut mode = Mode::Run;
+    let mut input = None;
 
-    pub fn make(value: String, position: Pos, kind: TokenKind) -> Self {
-        Self {
-            value,
-            position,
-            kind
+    while let Some(arg) = args.next() {
+        let next = match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "--tokens" => { mode = Mode::Tokens; continue },
+            "--ast" => { mode = Mode::Ast; continue },
+            "-e" => Input::Expr(args.next().ok_or("`-e` needs a source string")?),
+            "-" => Input::Stdin,
+            "-i" | "--repl" => Input::Repl,
+            flag if flag.starts_with('-') => return Err(format!("unknown option `{}`", flag)),
+            path => Input::File(path.to_string())
+        };
+
+        if input.replace(next).is_some() {
+            return Err("only one input may be given".to_string());
         }
     }
 
-    pub fn with_formatted_value<T: Display>(
-        value: &str,
-        sep: &str,
-        fmt_val: T,
-        pos: Pos,
-        kind: TokenKind
-    ) -> Self {
-        Token::make(format!("{}{}{}", value, sep, fmt_val), pos, kind)
+    let input = input.unwrap_or_else(|| {
+        if std::io::stdin().is_terminal() { Input::Repl } else { Input::Stdin }
+    });
+
+    if matches!(input, Input::Repl) && !matches!(mode, Mode::Run) {
+        return Err("`--tokens` and `--ast` are `:tokens` and `:ast` inside the REPL".to_string());
     }
-}
 
-impl Debug for Token {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "[{}]: {} ({:?})", self.position, self.value, self.kind)
+    Ok(Some(Options { mode, input }))
+}
+
+/// Name to report positions under, and the source text.
+fn read_input(input: Input) -> Result<(String, String), String> {
+    match input {
+        Input::File(path) => std::fs::read_to_string(&path)
+            .map(|text| (path.clone(), text))
+            .map_err(|e| format!("cannot read `{}`: {}", path, e)),
+        Input::Stdin => {
+            let mut text = String::new();
+            std::io::stdin().read_to_string(&mut text)
+                .map(|_| ("<stdin>".to_string(), text))
+                .map_err(|e| format!("cannot read standard input: {}", e))
+        },
+        Input::Expr(text) => Ok(("<expr>".to_string(), text)),
+        Input::Repl => unreachable!("the REPL reads its own input")
     }
 }
 
-static KEYWORDS: [&str; 3] = [ "let", "be", "fn"];
-static OPERATORS: [&str; 5] = ["=", "+", "-", "(", ")"];
-
-
-pub trait StringUtils {
-    fn trim_newlines(&self) -> Self;
-}
+/// Takes the source through as many stages as `mode` asks for and returns the exit code.
+fn execute(mode: Mode, sources: &SourceMap, file_id: usize) -> i32 {
+    let mut lexer = Lexer::new(sources.get(file_id), matches!(mode, Mode::Tokens));
 
-impl StringUtils for String {
-    fn trim_newlines(&self) -> String {
-        let mut s = self.clone();
-
-        while s.ends_with('\n') || s.ends_with('\r') {
-            if s.ends_with('\n') {
-                assert_eq!(s.pop(), Some('\n'));
-            }
-            if s.ends_with('\r') {
-                assert_eq!(s.pop(), Some('\r'));
-            }
+    if let Mode::Tokens = mode {
+        for token in lexer.by_ref() {
+            println!("{}", token.describe(sources.get(file_id)));
         }
-
-        s
     }
-}
 
+    let (program, errors) = parse(&mut lexer);
 
-fn determine_kind(token: String) -> TokenKind {
-    fn is_hex(word: String) -> bool {
-        let mut id = 0;
-        for c in word[2..].chars() {
-            if !c.is_digit(16) {
-                return false
-            }
-            id+=1;
-        }
-
-        if word.len() >= 2 {
-            if word.chars().nth(0) == Some('0') && word.chars().nth(1) == Some('x') {
-                return true;
-            }
-        }
-        false
+    // Parse errors after a malformed token would only be about the input ending there.
+    if let Some(e) = lexer.error() {
+        eprint!("{}", e.to_diagnostic().render(sources, use_colour()));
+        return EXIT_ERROR;
     }
 
-    for k in KEYWORDS {
-        if token.eq(k) {
-            return TokenKind::Keyword
-        }
+    if let Mode::Tokens = mode {
+        return 0;
     }
 
-    for o in OPERATORS {
-        if token.eq(o){
-            return TokenKind::Operator
-        }
+    for e in &errors {
+        eprint!("{}", e.to_diagnostic().render(sources, use_colour()));
     }
 
-    if token.parse::<f64>().is_ok() || is_hex(token) {
-        return TokenKind::Numeric
+    if !errors.is_empty() {
+        return EXIT_ERROR;
     }
 
-    TokenKind::Word
-}
-
-fn lex(text: String) -> Vec<Token> {
-    let mut tokens = Vec::new();
-    let mut curr = String::new();
-    let mut col = 0;
-    let mut start = 0;
-
-    for ch in text.chars() {
-        col += 1;
-        if ch != ' ' {
-            curr.push(ch);
-            continue;
+    if let Mode::Ast = mode {
+        for stmt in &program.statements {
+            println!("{}: {}", sources.get(file_id).pos(stmt.span.start), stmt.kind);
         }
+        return 0;
+    }
 
-        tokens.push(
-            Token::make(curr.clone().trim_newlines(),
-                        Pos::from_tuple((String::from("main.rs"), 0, start)),
-                        determine_kind(curr.clone().trim_newlines())
-            )
-        );
+    let (resolution, errors) = Resolver::new().resolve(&program);
 
-        start = col;
-        curr = String::new();
+    for e in &errors {
+        eprint!("{}", e.to_diagnostic().render(sources, use_colour()));
+    }
 
+    if !errors.is_empty() {
+        return EXIT_ERROR;
     }
 
-    tokens.push(
-                Token::make(curr.clone().trim_newlines(),
-                            Pos::from_tuple((String::from("main.rs"), 0, start)),
-                            determine_kind(curr.clone().trim_newlines())
-                )
-            );
+    let errors = Checker::new().check(&program, &resolution);
 
-    tokens
-}
+    for e in &errors {
+        eprint!("{}", e.to_diagnostic().render(sources, use_colour()));
+    }
 
-struct SyntaxNode {
-    value:      String,
-    text:       String,
-    position:   Pos,
-    kind:       TokenKind
-}
+    if !errors.is_empty() {
+        return EXIT_ERROR;
+    }
 
-fn parse(tokens: Vec<Token>) -> HashMap<String, String> {
-    let mut t_id = 0;
-    let mut hash = HashMap::new();
-    let mut last_key = String::new();
-
-    while t_id < tokens.len()-1 {
-        let curr_token = tokens[t_id].clone();
-        let next_token = tokens[t_id+1].clone();
-
-        match curr_token.kind {
-            TokenKind::Keyword => {
-                if curr_token.value.eq("let") {
-                    if next_token.kind != TokenKind::Word {
-                        panic!("Expected a word after \"let\" declaration at {}, got \"{}\" ({:?})",
-                               curr_token.position, next_token.value, next_token.kind)
-                    }
-                    t_id+=2;
-                    last_key = next_token.value.clone();
-                }
-                if curr_token.value.eq("be") {
-                    t_id+=2;
-                    hash.insert(last_key.clone(), next_token.value.clone());
-                }
-            },
-            TokenKind::Operator => {
-                if curr_token.value.eq("=") {
-                    t_id+=2;
-                    hash.insert(last_key.clone(), next_token.value.clone());
-                }
-            }
-            _ => {  //word, numeric
-                t_id+=1;
-            }
+    match Interpreter::new().run(&program) {
+        Ok(Value::Unit) => 0,
+        Ok(value) => {
+            println!("{}", value);
+            0
+        },
+        Err(e) => {
+            eprint!("{}", e.to_diagnostic().render(sources, use_colour()));
+            EXIT_ERROR
         }
     }
+}
 
-    if false {
-        for token in tokens {
-            println!("{:?}", token);
+/// Everything `main` does, on a thread with `STACK_SIZE` of stack. Returns the exit code.
+fn start() -> i32 {
+    let options = match parse_args(std::env::args().skip(1)) {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            println!("{}", USAGE);
+            return 0;
+        },
+        Err(message) => {
+            eprintln!("error: {}\n\n{}", message, USAGE);
+            return EXIT_USAGE;
         }
-    }
+    };
 
-    hash
-}
+    if let Input::Repl = options.input {
+        Repl::new().run();
+        return 0;
+    }
 
-fn main() {
-    let tokens: Vec<Token> = lex(String::from(
-        "let it be 0.654876418768547946\n \
-        let\n\r hex be 0xfb00be\
-        let a be hex"));
+    let (name, text) = match read_input(options.input) {
+        Ok(input) => input,
+        Err(message) => {
+            eprintln!("error: {}", message);
+            return EXIT_USAGE;
+        }
+    };
 
-    let vars = parse(tokens);
+    let mut sources = SourceMap::new();
+    let file_id = sources.add(&name, &text);
 
-    println!("{}", vars.len());
+    execute(options.mode, &sources, file_id)
+}
 
-    for var in vars {
-        println!("{} : {}", var.0, var.1);
-    }
+fn main() {
+    let code = std::thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(start)
+        .expect("failed to start the interpreter thread")
+        .join()
+        .unwrap_or_else(|panic| std::panic::resume_unwind(panic));
+
+    std::process::exit(code);
 }
@@ -0,0 +1,846 @@
+use std::collections::VecDeque;
+use std::rc::Rc;
+
+use crate::ast::{Expr, ExprKind, FnDef, Ident, Param, Program, Stmt, StmtKind, SyntaxNode, TypeExpr, TypeExprKind};
+use crate::error::ParseError;
+use crate::lexer::{normalize, split_suffix, unescape, INT_SUFFIXES};
+use crate::span::Span;
+use crate::stack;
+use crate::symbol::Symbol;
+use crate::{OperatorKind, Token, TokenKind};
+
+type ParseResult<T> = Result<T, Box<ParseError>>;
+
+/// How many tokens past the current one `peek_nth` can see.
+const LOOKAHEAD: usize = 2;
+
+/// The most stack any pass over the syntax tree takes per level of it, in a debug build.
+/// Every pass recurses once per level, so the tree may only get as deep as the stack left
+/// when parsing starts can take them, see `Parser::max_nesting`.
+const STACK_PER_LEVEL: usize = 16 << 10;
+
+/// What is still needed of the last consumed token, which itself was handed to the caller.
+struct Prev<'a> {
+    value: &'a str,
+    span:  Span
+}
+
+/// Recursive descent over a stream of tokens, pulled only as far as `LOOKAHEAD` needs.
+/// Errors are collected in `errors` and parsing resumes at the next statement,
+/// so one pass reports all of them.
+/// `t_id` counts the tokens consumed so far, `prev` is the last of them.
+/// `fn_depth` counts the function bodies being parsed, `return` is only valid inside one.
+/// `loop_depth` counts the loop bodies inside the innermost function, for `break` and `continue`.
+/// `block_depth` counts every open `{`, `nesting` how deep the tree being built is,
+/// which may not exceed `max_nesting`.
+struct Parser<'a, I: Iterator<Item = Token<'a>>> {
+    tokens:      I,
+    lookahead:   VecDeque<Token<'a>>,
+    prev:        Option<Prev<'a>>,
+    t_id:        usize,
+    errors:      Vec<ParseError>,
+    fn_depth:    usize,
+    loop_depth:  usize,
+    block_depth: usize,
+    nesting:     usize,
+    max_nesting: usize
+}
+
+impl<'a, I: Iterator<Item = Token<'a>>> Parser<'a, I> {
+    pub fn new(tokens: I) -> Self {
+        let mut parser = Self {
+            tokens,
+            lookahead:   VecDeque::with_capacity(LOOKAHEAD),
+            prev:        None,
+            t_id:        0,
+            errors:      Vec::new(),
+            fn_depth:    0,
+            loop_depth:  0,
+            block_depth: 0,
+            nesting:     0,
+            max_nesting: stack::remaining() / STACK_PER_LEVEL
+        };
+        parser.fill();
+        parser
+    }
+
+    /// Tops `lookahead` up from the stream; comments mean nothing to the parser.
+    fn fill(&mut self) {
+        while self.lookahead.len() < LOOKAHEAD {
+            match self.tokens.find(|t| t.kind != TokenKind::Comment) {
+                Some(token) => self.lookahead.push_back(token),
+                None => break
+            }
+        }
+    }
+
+    fn peek(&self) -> Option<&Token<'a>> { self.lookahead.front() }
+
+    fn peek_nth(&self, n: usize) -> Option<&Token<'a>> { self.lookahead.get(n) }
+
+    fn prev(&self) -> Option<&Prev<'a>> { self.prev.as_ref() }
+
+    fn at(&self, kind: TokenKind, value: &str) -> bool {
+        self.peek().is_some_and(|t| t.kind == kind && t.value.eq(value))
+    }
+
+    fn advance(&mut self) -> Option<Token<'a>> {
+        let token = self.lookahead.pop_front()?;
+        self.fill();
+        self.t_id+=1;
+        self.prev = Some(Prev { value: token.value, span: token.span });
+        Some(token)
+    }
+
+    /// Error for the current token (or the end of input) not being one of `expected`.
+    fn unexpected(&self, expected: &[TokenKind]) -> Box<ParseError> {
+        let prev = self.prev();
+
+        Box::new(match (self.peek(), prev) {
+            (Some(token), _) => ParseError::UnexpectedToken {
+                expected: expected.to_vec(),
+                found:    token.kind.clone(),
+                value:    token.value.to_string(),
+                after:    prev.map(|p| p.value.to_string()),
+                span:     token.span,
+                prev:     prev.map(|p| p.span)
+            },
+            (None, Some(prev)) => ParseError::UnexpectedEof {
+                expected: expected.to_vec(),
+                after:    prev.value.to_string(),
+                span:     Span::make(prev.span.file_id, prev.span.end, prev.span.end),
+                prev:     prev.span
+            },
+            (None, None) => unreachable!("statements are only parsed while tokens remain")
+        })
+    }
+
+    /// Consumes the current token if it is one of `expected`.
+    fn expect(&mut self, expected: &[TokenKind]) -> ParseResult<Token<'a>> {
+        match self.peek() {
+            Some(token) if expected.contains(&token.kind) => Ok(self.advance().unwrap()),
+            _ => Err(self.unexpected(expected))
+        }
+    }
+
+    /// Consumes the operator `symbol`.
+    fn expect_symbol(&mut self, symbol: &str) -> ParseResult<Token<'a>> {
+        if self.at(TokenKind::Operator, symbol) {
+            return Ok(self.advance().unwrap());
+        }
+
+        let (found, span) = match (self.peek(), self.prev()) {
+            (Some(token), _) => (Some(token.value.to_string()), token.span),
+            (None, Some(prev)) => (None, Span::make(prev.span.file_id, prev.span.end, prev.span.end)),
+            (None, None) => unreachable!("statements are only parsed while tokens remain")
+        };
+
+        Err(Box::new(ParseError::ExpectedSymbol { symbol: symbol.to_string(), found, span }))
+    }
+
+    /// Goes one level deeper into the tree, unless that is past `max_nesting`.
+    /// Whoever calls this puts `nesting` back once the level is built; after an error,
+    /// the statement that failed does.
+    fn nest(&mut self) -> ParseResult<()> {
+        self.nesting+=1;
+
+        if self.nesting <= self.max_nesting && !stack::exhausted() {
+            return Ok(());
+        }
+
+        // Something was consumed to get this deep.
+        let span = self.prev().unwrap().span;
+
+        // Nothing after this point is parsed at a depth that would work either, so the rest of the input is skipped.
+        self.lookahead.clear();
+        self.tokens.by_ref().for_each(drop);
+
+        Err(Box::new(ParseError::TooDeep { limit: self.max_nesting, span }))
+    }
+
+    /// Records an error for a statement that failed to parse. Nothing is recorded after
+    /// `ParseError::TooDeep`: that left every construct around it without its end.
+    fn report(&mut self, e: ParseError) {
+        if !self.errors.last().is_some_and(|last| matches!(last, ParseError::TooDeep { .. })) {
+            self.errors.push(e);
+        }
+    }
+
+    /// Skips to the first token that can start a fresh statement: a `let`/`fn` keyword,
+    /// a `}` closing the enclosing block or anything on a later line than the offending token.
+    fn synchronize(&mut self, start: usize) {
+        let mut first = true;
+
+        while let Some(token) = self.peek() {
+            if (token.newline_before && !first) || token.value.eq("let") || token.value.eq("fn")
+                || (self.block_depth > 0 && token.value.eq("}")) {
+                break;
+            }
+            self.advance();
+            first = false;
+        }
+
+        // Never stop on the token the failed statement started at.
+        if self.t_id == start {
+            self.advance();
+        }
+    }
+
+    /// A statement has to end its line unless a `let` or `fn` or the `}` of its block follows:
+    /// `let a = 1 2` is a mistake rather than two statements. That is reported and the rest of the line skipped.
+    fn end_statement(&mut self) {
+        let Some(token) = self.peek() else {
+            return;
+        };
+
+        let ends = token.newline_before
+            || (token.kind == TokenKind::Keyword && matches!(token.value, "let" | "fn"))
+            || (self.block_depth > 0 && token.kind == TokenKind::Operator && token.value.eq("}"));
+
+        if !ends {
+            let e = self.unexpected(&[TokenKind::Operator]);
+            self.report(*e);
+            self.synchronize(self.t_id);
+        }
+    }
+
+    pub fn program(&mut self) -> Program {
+        let mut program = Program::default();
+
+        while self.peek().is_some() {
+            let start = self.t_id;
+
+            match self.statement() {
+                Ok(stmt) => {
+                    program.statements.push(stmt);
+                    self.end_statement();
+                },
+                Err(e) => {
+                    self.report(*e);
+                    self.nesting = 0;
+                    self.synchronize(start);
+                }
+            }
+        }
+
+        program
+    }
+
+    fn statement(&mut self) -> ParseResult<Stmt> {
+        if self.at(TokenKind::Keyword, "let") {
+            return self.let_statement();
+        }
+
+        if self.at(TokenKind::Keyword, "fn") && self.peek_nth(1).is_some_and(|t| t.kind == TokenKind::Word) {
+            let (keyword, def) = self.fn_definition(true)?;
+            let span = keyword.span.to(self.prev().unwrap().span);
+
+            return Ok(SyntaxNode::make(StmtKind::Fn(Rc::new(def)), span));
+        }
+
+        if self.peek().is_some_and(|t| t.kind == TokenKind::Keyword && matches!(t.value, "return" | "break" | "continue")) {
+            return self.jump_statement();
+        }
+
+        if self.peek().is_some_and(|t| t.kind == TokenKind::Word)
+            && self.peek_nth(1).is_some_and(|t| t.kind == TokenKind::Operator && matches!(t.value, "=" | "+=" | "-=" | "*=" | "/=")) {
+            return self.assignment();
+        }
+
+        if self.peek().is_some_and(|t| {
+            t.kind == TokenKind::Keyword && !matches!(t.value, "fn" | "if" | "while" | "for" | "true" | "false")
+        }) {
+            return Err(self.unexpected(&[TokenKind::Keyword, TokenKind::Word, TokenKind::Numeric]));
+        }
+
+        let expr = self.expression()?;
+        let span = expr.span;
+        Ok(SyntaxNode::make(StmtKind::Expr(expr), span))
+    }
+
+    /// `let <name> be <value>` or `let <name> = <value>`, with `mut` before the name
+    /// if the binding may be assigned to later and an optional `: <type>` after it.
+    fn let_statement(&mut self) -> ParseResult<Stmt> {
+        let keyword = self.advance().unwrap();
+        let mutable = self.at(TokenKind::Keyword, "mut");
+        if mutable {
+            self.advance();
+        }
+        let name = self.expect(&[TokenKind::Word])?;
+        let ty = self.annotation(":")?;
+
+        if !self.at(TokenKind::Keyword, "be") && !self.at(TokenKind::Operator, "=") {
+            return Err(self.unexpected(&[TokenKind::Keyword, TokenKind::Operator]));
+        }
+        self.advance();
+
+        let value = self.expression()?;
+        let span = keyword.span.to(value.span);
+
+        Ok(SyntaxNode::make(StmtKind::Let { name: ident(name), mutable, ty, value }, span))
+    }
+
+    /// `<name> = <value>`, or a compound `<name> += <value>` and the like.
+    fn assignment(&mut self) -> ParseResult<Stmt> {
+        let target = ident(self.advance().unwrap());
+        let op = OperatorKind::from_value(self.advance().unwrap().value).and_then(OperatorKind::compound);
+
+        let value = self.expression()?;
+        let span = target.span.to(value.span);
+
+        Ok(SyntaxNode::make(StmtKind::Assign { target, op, value }, span))
+    }
+
+    /// `fn <name>(<params>) -> <type> { <body> }`, or without the name when used as an expression.
+    /// Parameters and the return type may be annotated or not.
+    fn fn_definition(&mut self, named: bool) -> ParseResult<(Token<'a>, FnDef)> {
+        let keyword = self.advance().unwrap();
+        let name = if named { Some(ident(self.expect(&[TokenKind::Word])?)) } else { None };
+
+        let open = self.expect_symbol("(")?;
+        let params = self.comma_list(&open, ")", |p| {
+            let name = ident(p.expect(&[TokenKind::Word])?);
+            Ok(Param { name, ty: p.annotation(":")? })
+        })?;
+        let ret = self.annotation("->")?;
+
+        let body_open = self.expect_symbol("{")?;
+        self.nest()?;
+        // A loop around the definition is not one `break` inside the body could leave.
+        self.fn_depth+=1;
+        let loop_depth = std::mem::take(&mut self.loop_depth);
+        let body = self.block_body(&body_open);
+        self.loop_depth = loop_depth;
+        self.fn_depth-=1;
+        self.nesting-=1;
+
+        Ok((keyword, FnDef { name, params, ret, body: body? }))
+    }
+
+    /// The type after `symbol` (`:` or `->`) if the current token is `symbol`.
+    fn annotation(&mut self, symbol: &str) -> ParseResult<Option<TypeExpr>> {
+        if !self.at(TokenKind::Operator, symbol) {
+            return Ok(None);
+        }
+        self.advance();
+
+        self.type_expr().map(Some)
+    }
+
+    /// A type name, or `fn(<types>)` with an optional `-> <type>`.
+    fn type_expr(&mut self) -> ParseResult<TypeExpr> {
+        if self.at(TokenKind::Keyword, "fn") {
+            let keyword = self.advance().unwrap();
+            self.nest()?;
+            let open = self.expect_symbol("(")?;
+            let params = self.comma_list(&open, ")", |p| p.type_expr())?;
+            let ret = self.annotation("->")?.map(Box::new);
+            self.nesting-=1;
+
+            let span = keyword.span.to(self.prev().unwrap().span);
+            return Ok(SyntaxNode::make(TypeExprKind::Fn { params, ret }, span));
+        }
+
+        let name = ident(self.expect(&[TokenKind::Word])?);
+        Ok(SyntaxNode::make(TypeExprKind::Named(name.kind), name.span))
+    }
+
+    /// Statements up to and including the `}` matching `open`.
+    fn block_body(&mut self, open: &Token) -> ParseResult<Vec<Stmt>> {
+        self.block_depth+=1;
+        let statements = self.block_statements();
+        self.block_depth-=1;
+
+        self.expect_closing(open, "}")?;
+        Ok(statements)
+    }
+
+    fn block_statements(&mut self) -> Vec<Stmt> {
+        let mut statements = Vec::new();
+
+        while self.peek().is_some_and(|t| !(t.kind == TokenKind::Operator && t.value.eq("}"))) {
+            let (start, nesting) = (self.t_id, self.nesting);
+
+            match self.statement() {
+                Ok(stmt) => {
+                    statements.push(stmt);
+                    self.end_statement();
+                },
+                Err(e) => {
+                    self.report(*e);
+                    self.nesting = nesting;
+                    self.synchronize(start);
+                }
+            }
+        }
+
+        statements
+    }
+
+    /// `item`s separated by `,` up to and including `close`; a trailing comma is allowed.
+    fn comma_list<T>(&mut self, open: &Token, close: &str,
+                     mut item: impl FnMut(&mut Self) -> ParseResult<T>) -> ParseResult<Vec<T>> {
+        let mut items = Vec::new();
+
+        while !self.at(TokenKind::Operator, close) {
+            items.push(item(self)?);
+
+            if !self.at(TokenKind::Operator, ",") {
+                break;
+            }
+            self.advance();
+        }
+
+        self.expect_closing(open, close)?;
+        Ok(items)
+    }
+
+    /// `return` with an optional value on the same line, or a bare `break` or `continue`.
+    /// Each is only valid inside the function or loop it leaves.
+    fn jump_statement(&mut self) -> ParseResult<Stmt> {
+        let keyword = self.advance().unwrap();
+        let (depth, outside) = if keyword.value.eq("return") { (self.fn_depth, "function") } else { (self.loop_depth, "loop") };
+
+        if depth == 0 {
+            return Err(Box::new(ParseError::Misplaced {
+                keyword: keyword.value.to_string(),
+                outside,
+                span:    keyword.span
+            }));
+        }
+
+        if keyword.value.eq("continue") {
+            return Ok(SyntaxNode::make(StmtKind::Continue, keyword.span));
+        }
+
+        let has_value = self.peek().is_some_and(|t| {
+            !(t.newline_before || (t.kind == TokenKind::Operator && t.value.eq("}")))
+        });
+        let value = if has_value { Some(self.expression()?) } else { None };
+
+        if keyword.value.eq("break") {
+            return match value {
+                Some(value) => Err(Box::new(ParseError::BreakValue { span: value.span })),
+                None => Ok(SyntaxNode::make(StmtKind::Break, keyword.span))
+            };
+        }
+
+        let span = value.as_ref().map_or(keyword.span, |v| keyword.span.to(v.span));
+        Ok(SyntaxNode::make(StmtKind::Return(value), span))
+    }
+
+    fn expression(&mut self) -> ParseResult<Expr> { self.expression_bp(0) }
+
+    /// Precedence climbing: keeps folding infix operators into `lhs`
+    /// as long as they bind at least as tightly as `min_bp`.
+    /// A line break before the operator ends the expression, so a line may not start with one.
+    fn expression_bp(&mut self, min_bp: u8) -> ParseResult<Expr> {
+        let nesting = self.nesting;
+        self.nest()?;
+
+        let prefix = self.prefix()?;
+        let mut lhs = self.calls(prefix)?;
+
+        while let Some(op) = self.peek_operator() {
+            if self.peek().unwrap().newline_before {
+                break;
+            }
+            let Some((l_bp, r_bp)) = infix_binding_power(op) else {
+                break;
+            };
+            if l_bp < min_bp {
+                break;
+            }
+            self.advance();
+            // Each operator folded in puts everything before it one level further down.
+            self.nest()?;
+
+            let rhs = self.expression_bp(r_bp)?;
+            let span = lhs.span.to(rhs.span);
+            lhs = SyntaxNode::make(ExprKind::Binary { op, lhs: Box::new(lhs), rhs: Box::new(rhs) }, span);
+        }
+
+        self.nesting = nesting;
+        Ok(lhs)
+    }
+
+    fn peek_operator(&self) -> Option<OperatorKind> {
+        self.peek()
+            .filter(|t| t.kind == TokenKind::Operator)
+            .and_then(|t| OperatorKind::from_value(t.value))
+    }
+
+    /// `callee(<args>)`, possibly chained. The `(` has to be on the line the callee ends on,
+    /// otherwise it starts a new statement.
+    fn calls(&mut self, mut callee: Expr) -> ParseResult<Expr> {
+        while self.at(TokenKind::Operator, "(") && !self.peek().unwrap().newline_before {
+            let open = self.advance().unwrap();
+            // Like an infix operator, each call in the chain is a level; `expression_bp` undoes them.
+            self.nest()?;
+            let args = self.comma_list(&open, ")", |p| p.expression())?;
+
+            let span = callee.span.to(self.prev().unwrap().span);
+            callee = SyntaxNode::make(ExprKind::Call { callee: Box::new(callee), args }, span);
+        }
+
+        Ok(callee)
+    }
+
+    /// Literals, names, anonymous functions, `if`, loops, blocks, unary `-`/`!` and parenthesised sub-expressions.
+    fn prefix(&mut self) -> ParseResult<Expr> {
+        if self.at(TokenKind::Keyword, "fn") {
+            let (keyword, def) = self.fn_definition(false)?;
+            let span = keyword.span.to(self.prev().unwrap().span);
+
+            return Ok(SyntaxNode::make(ExprKind::Fn(Rc::new(def)), span));
+        }
+
+        if self.at(TokenKind::Keyword, "if") {
+            return self.if_expression();
+        }
+
+        if self.at(TokenKind::Keyword, "while") {
+            let keyword = self.advance().unwrap();
+            let cond = self.expression()?;
+            let body = self.loop_body()?;
+
+            let span = keyword.span.to(self.prev().unwrap().span);
+            return Ok(SyntaxNode::make(ExprKind::While { cond: Box::new(cond), body }, span));
+        }
+
+        if self.at(TokenKind::Keyword, "for") {
+            return self.for_expression();
+        }
+
+        if self.at(TokenKind::Keyword, "true") || self.at(TokenKind::Keyword, "false") {
+            let token = self.advance().unwrap();
+            return Ok(SyntaxNode::make(ExprKind::Bool(token.value.eq("true")), token.span));
+        }
+
+        if let Some(op @ (OperatorKind::Minus | OperatorKind::Not)) = self.peek_operator() {
+            let token = self.advance().unwrap();
+
+            if op == OperatorKind::Minus && self.peek().is_some_and(|t| t.kind == TokenKind::Numeric) {
+                let literal = self.advance().unwrap();
+                let kind = number(&literal, Some(&token))?;
+                return Ok(SyntaxNode::make(kind, token.span.to(literal.span)));
+            }
+            let operand = self.expression_bp(PREFIX_BP)?;
+            let span = token.span.to(operand.span);
+
+            return Ok(SyntaxNode::make(ExprKind::Unary { op, operand: Box::new(operand) }, span));
+        }
+
+        if self.at(TokenKind::Operator, "{") {
+            let open = self.advance().unwrap();
+            let body = self.block_body(&open)?;
+
+            let span = open.span.to(self.prev().unwrap().span);
+            return Ok(SyntaxNode::make(ExprKind::Block(body), span));
+        }
+
+        if self.at(TokenKind::Operator, "(") {
+            let open = self.advance().unwrap();
+            let inner = self.expression()?;
+            let close = self.expect_closing(&open, ")")?;
+
+            return Ok(SyntaxNode::make(inner.kind, open.span.to(close.span)));
+        }
+
+        let token = self.expect(&[TokenKind::Word, TokenKind::Numeric, TokenKind::String, TokenKind::Char])?;
+
+        let kind = match token.kind {
+            TokenKind::Numeric => number(&token, None)?,
+            TokenKind::String => ExprKind::Str(unescape(token.value)),
+            TokenKind::Char => ExprKind::Char(unescape(token.value).chars().next().unwrap_or_default()),
+            _ => ExprKind::Ident(Symbol::intern(&normalize(token.value)))
+        };
+
+        Ok(SyntaxNode::make(kind, token.span))
+    }
+
+    /// `if <cond> { ... }`, optionally followed by `else { ... }` or `else if ...`.
+    /// The `else` may start a new line.
+    fn if_expression(&mut self) -> ParseResult<Expr> {
+        let keyword = self.advance().unwrap();
+        let cond = self.expression()?;
+
+        let open = self.expect_symbol("{")?;
+        let then = self.block_body(&open)?;
+
+        let otherwise = if self.at(TokenKind::Keyword, "else") {
+            self.advance();
+
+            if self.at(TokenKind::Keyword, "if") {
+                self.nest()?;
+                let nested = self.if_expression()?;
+                self.nesting-=1;
+                let span = nested.span;
+                Some(vec![SyntaxNode::make(StmtKind::Expr(nested), span)])
+            } else {
+                let open = self.expect_symbol("{")?;
+                Some(self.block_body(&open)?)
+            }
+        } else {
+            None
+        };
+
+        let span = keyword.span.to(self.prev().unwrap().span);
+        let kind = ExprKind::If { cond: Box::new(cond), then, otherwise };
+
+        Ok(SyntaxNode::make(kind, span))
+    }
+
+    /// `for <name> in <iterable> { ... }`.
+    fn for_expression(&mut self) -> ParseResult<Expr> {
+        let keyword = self.advance().unwrap();
+        let var = ident(self.expect(&[TokenKind::Word])?);
+
+        if !self.at(TokenKind::Keyword, "in") {
+            return Err(self.unexpected(&[TokenKind::Keyword]));
+        }
+        self.advance();
+
+        let iter = self.expression()?;
+        let body = self.loop_body()?;
+
+        let span = keyword.span.to(self.prev().unwrap().span);
+        Ok(SyntaxNode::make(ExprKind::For { var, iter: Box::new(iter), body }, span))
+    }
+
+    /// The `{ ... }` of a loop, where `break` and `continue` are allowed.
+    fn loop_body(&mut self) -> ParseResult<Vec<Stmt>> {
+        let open = self.expect_symbol("{")?;
+
+        self.loop_depth+=1;
+        let body = self.block_body(&open);
+        self.loop_depth-=1;
+
+        body
+    }
+
+    /// Consumes `close`, or reports that the delimiter `open` was never closed.
+    fn expect_closing(&mut self, open: &Token, close: &str) -> ParseResult<Token<'a>> {
+        if self.at(TokenKind::Operator, close) {
+            return Ok(self.advance().unwrap());
+        }
+
+        let span = match (self.peek(), self.prev()) {
+            (Some(token), _) => token.span,
+            (None, Some(prev)) => Span::make(prev.span.file_id, prev.span.end, prev.span.end),
+            (None, None) => unreachable!("`open` was consumed")
+        };
+
+        Err(Box::new(ParseError::UnclosedDelimiter {
+            open:    open.value.to_string(),
+            close:   close.to_string(),
+            span,
+            open_at: open.span
+        }))
+    }
+}
+
+const PREFIX_BP: u8 = 13;
+
+/// Loosest to tightest: `||`, `&&`, comparisons, `..`, `+ -`, `* /`. All of them associate to the left.
+fn infix_binding_power(op: OperatorKind) -> Option<(u8, u8)> {
+    match op {
+        OperatorKind::Or => Some((1, 2)),
+        OperatorKind::And => Some((3, 4)),
+        OperatorKind::EqEq | OperatorKind::NotEq
+        | OperatorKind::Lt | OperatorKind::LtEq
+        | OperatorKind::Gt | OperatorKind::GtEq => Some((5, 6)),
+        OperatorKind::Range => Some((7, 8)),
+        OperatorKind::Plus | OperatorKind::Minus => Some((9, 10)),
+        OperatorKind::Mul | OperatorKind::Div => Some((11, 12)),
+        OperatorKind::Eq | OperatorKind::Not
+        | OperatorKind::PlusEq | OperatorKind::MinusEq
+        | OperatorKind::MulEq | OperatorKind::DivEq => None
+    }
+}
+
+/// Integer literals become `Int` and have to fit both their suffix type and `i64`, which every
+/// integer is at runtime: a suffix only narrows the range of the literal, `255u8 + 1` is `256`.
+/// Anything with a fraction, an exponent or a float suffix becomes a `Float`.
+/// `minus` is a `-` right before the literal, which makes it negative; that way
+/// `-9223372036854775808` is `i64::MIN` rather than a negated literal too large for an `i64`.
+fn number(token: &Token, minus: Option<&Token>) -> ParseResult<ExprKind> {
+    let text = token.value.replace('_', "");
+    let (body, suffix) = split_suffix(&text);
+
+    let (radix, digits) = match body.get(..2) {
+        Some("0x") => (16, &body[2..]),
+        Some("0o") => (8, &body[2..]),
+        Some("0b") => (2, &body[2..]),
+        _ => (10, body)
+    };
+
+    if suffix.starts_with('f') || (radix == 10 && digits.contains(['.', 'e', 'E'])) {
+        let value: f64 = digits.parse().unwrap_or(f64::NAN);
+        let value = if suffix == "f32" { value as f32 as f64 } else { value };
+        return Ok(ExprKind::Float(if minus.is_some() { -value } else { value }));
+    }
+
+    let out_of_range = |ty| Box::new(ParseError::InvalidLiteral {
+        text: format!("{}{}", if minus.is_some() { "-" } else { "" }, token.value),
+        ty,
+        span: minus.map_or(token.span, |m| m.span.to(token.span))
+    });
+
+    let (ty, max) = match suffix {
+        "i8" => ("i8", i8::MAX as u64),
+        "i16" => ("i16", i16::MAX as u64),
+        "i32" => ("i32", i32::MAX as u64),
+        "u8" => ("u8", u8::MAX as u64),
+        "u16" => ("u16", u16::MAX as u64),
+        "u32" => ("u32", u32::MAX as u64),
+        // Wider than what the literal becomes, so that is the limit.
+        _ => ("i64", i64::MAX as u64)
+    };
+    let value = u64::from_str_radix(digits, radix).map_err(|_| out_of_range(ty))?;
+
+    if minus.is_some() && suffix.starts_with('u') && value > 0 {
+        return Err(out_of_range(INT_SUFFIXES.iter().find(|s| **s == suffix).unwrap()));
+    }
+    // Signed types reach one further below zero than above it.
+    if value > if minus.is_some() { max + 1 } else { max } {
+        return Err(out_of_range(ty));
+    }
+
+    Ok(ExprKind::Int(if minus.is_some() { (value as i64).wrapping_neg() } else { value as i64 }))
+}
+
+fn ident(token: Token) -> Ident {
+    SyntaxNode::make(Symbol::intern(&normalize(token.value)), token.span)
+}
+
+/// Builds the syntax tree of `tokens`, which may be a `Vec` or a `Lexer` read as it goes.
+/// Statements that fail to parse are left out of the `Program` and reported in the returned errors instead.
+pub fn parse<'a>(tokens: impl IntoIterator<Item = Token<'a>>) -> (Program, Vec<ParseError>) {
+    let mut parser = Parser::new(tokens.into_iter());
+    let program = parser.program();
+
+    (program, parser.errors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lex, testing};
+    use crate::span::SourceMap;
+
+    /// Every statement of `text` as `Display` prints it, and the codes of the errors.
+    fn parsed(text: &str) -> (Vec<String>, Vec<&'static str>) {
+        let mut sources = SourceMap::new();
+        let file_id = sources.add("test", text);
+        let (program, errors) = parse(lex(sources.get(file_id), false).unwrap());
+
+        (program.statements.iter().map(|stmt| stmt.kind.to_string()).collect(),
+         errors.iter().map(|e| e.code()).collect())
+    }
+
+    fn statements(text: &str) -> Vec<String> {
+        let (statements, errors) = parsed(text);
+        assert_eq!(errors, Vec::<&str>::new(), "errors in {:?}", text);
+        statements
+    }
+
+    fn errors(text: &str) -> Vec<&'static str> { parsed(text).1 }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(statements("let a = 1 + 2 * 3 - 4"), vec!["let a be ((1 + (2 * 3)) - 4)"]);
+        assert_eq!(statements("a || b && c == 1 .. 2"), vec!["(a || (b && (c == (1 .. 2))))"]);
+        assert_eq!(statements("-a * !b"), vec!["((-a) * (!b))"]);
+        assert_eq!(statements("f(1)(2)"), vec!["f(1)(2)"]);
+    }
+
+    #[test]
+    fn line_breaks() {
+        assert_eq!(statements("let a = 1\n-2"), vec!["let a be 1", "-2"]);
+        assert_eq!(statements("let b = 1 +\n  2"), vec!["let b be (1 + 2)"]);
+        assert_eq!(statements("f\n(1)"), vec!["f", "1"]);
+        assert_eq!(statements("fn f() { return\n1 }"), vec!["fn f() { return; 1; }"]);
+    }
+
+    #[test]
+    fn recovery() {
+        assert_eq!(parsed("let = 1\nlet b = 2\nlet c = )"), (vec!["let b be 2".to_string()], vec!["P0001", "P0001"]));
+        assert_eq!(errors("(1 + 2"), vec!["P0003"]);
+        assert_eq!(errors("fn f( { 1 }"), vec!["P0001"]);
+    }
+
+    #[test]
+    fn statements_end_their_line() {
+        assert_eq!(parsed("let a = 1 2\nlet b = 3"), (vec!["let a be 1".to_string(), "let b be 3".to_string()], vec!["P0001"]));
+        assert_eq!(errors("f() g()"), vec!["P0001"]);
+        assert_eq!(errors("{ 1 2 }"), vec!["P0001"]);
+        assert_eq!(errors("1 }"), vec!["P0001"]);
+        assert_eq!(statements("let a = 1 let b = 2 fn f() { a }"), vec!["let a be 1", "let b be 2", "fn f() { a; }"]);
+    }
+
+    #[test]
+    fn nesting_limit() {
+        let nested = |depth: usize| format!("{}1{}", "(".repeat(depth), ")".repeat(depth));
+
+        assert_eq!(errors(&nested(50)), Vec::<&str>::new());
+        assert_eq!(errors(&nested(50_000)), vec!["P0007"]);
+        assert_eq!(errors(&vec!["1"; 50_000].join(" + ")), vec!["P0007"]);
+        assert_eq!(errors(&format!("{}1{}", "{ ".repeat(50_000), " }".repeat(50_000))), vec!["P0007"]);
+    }
+
+    #[test]
+    fn deepest_trees_fit_every_pass() {
+        let shapes: [fn(usize) -> String; 4] = [
+            |depth| format!("{}1{}", "(".repeat(depth), ")".repeat(depth)),
+            |depth| format!("{}1{}", "{ ".repeat(depth), " }".repeat(depth)),
+            |depth| vec!["1"; depth].join(" + "),
+            |depth| format!("fn f(x) {{ x }}\n{}1{}", "f(".repeat(depth), ")".repeat(depth))
+        ];
+
+        for shape in shapes {
+            // The deepest one this thread can parse.
+            let (mut ok, mut too_deep) = (1, 100_000);
+            while ok + 1 < too_deep {
+                let depth = (ok + too_deep) / 2;
+                if errors(&shape(depth)).is_empty() { ok = depth } else { too_deep = depth }
+            }
+
+            assert!(ok > 50);
+            // Running out of stack while running it is an error like any other.
+            if let Err(codes) = testing::run(&shape(ok)) {
+                assert_eq!(codes, vec!["R0007"]);
+            }
+        }
+    }
+
+    #[test]
+    fn integer_literals() {
+        assert_eq!(statements("let m = -9223372036854775808"), vec!["let m be -9223372036854775808"]);
+        assert_eq!(statements("let b = 255u8"), vec!["let b be 255"]);
+        assert_eq!(errors("let m = 9223372036854775808"), vec!["P0004"]);
+        assert_eq!(errors("let m = -9223372036854775809"), vec!["P0004"]);
+        assert_eq!(errors("let b = 256u8"), vec!["P0004"]);
+        assert_eq!(errors("let b = -5u8"), vec!["P0004"]);
+        assert_eq!(statements("let b = -0u8"), vec!["let b be 0"]);
+        assert_eq!(errors("let b = -129i8"), vec!["P0004"]);
+        assert_eq!(statements("let b = -128i8"), vec!["let b be -128"]);
+        assert_eq!(errors("let b = 18446744073709551615u64"), vec!["P0004"]);
+    }
+
+    #[test]
+    fn misplaced_return() {
+        assert_eq!(errors("return 1"), vec!["P0005"]);
+        assert_eq!(statements("fn f() { return 1 }"), vec!["fn f() { return 1; }"]);
+    }
+
+    #[test]
+    fn misplaced_jumps() {
+        assert_eq!(errors("break"), vec!["P0005"]);
+        assert_eq!(errors("continue"), vec!["P0005"]);
+        assert_eq!(errors("while true { fn f() { continue } }"), vec!["P0005"]);
+        assert_eq!(statements("while true { break }"), vec!["while true { break; }"]);
+        assert_eq!(errors("while true { break 5 }"), vec!["P0008"]);
+        assert_eq!(statements("for i in 0..3 { if i == 1 { continue } }"), vec!["for i in (0 .. 3) { if (i == 1) { continue; }; }"]);
+    }
+}
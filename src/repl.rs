@@ -0,0 +1,271 @@
+use std::io::{BufRead, Write};
+
+use lexing::ast::Program;
+use lexing::span::SourceMap;
+use lexing::value::Value;
+use lexing::{lex, parse, Checker, Interpreter, Lexer, Resolver};
+
+use crate::use_colour;
+
+const HELP: &str = "\
+:tokens <source>  print the tokens of <source>
+:ast <source>     print the syntax tree of <source>
+:env              list every binding with its value and type
+:reset            forget every binding
+:help             print this message
+:quit             leave, as does end of input
+
+A line that leaves a statement unfinished, like an open `(`, is continued on the next one.
+An empty line gives up and reports what is missing.";
+
+/// What became of the text entered so far.
+enum Chunk {
+    Complete(Program),
+    Incomplete,
+    Failed
+}
+
+/// Reads statements line by line and runs them against one `Resolver`, `Checker` and `Interpreter`,
+/// so bindings made on one line are visible on the next.
+/// Every entry stays in `sources`, since closures defined earlier may fail later.
+pub struct Repl {
+    sources:     SourceMap,
+    resolver:    Resolver,
+    checker:     Checker,
+    interpreter: Interpreter,
+    entries:     usize
+}
+
+impl Repl {
+    pub fn new() -> Self {
+        Self {
+            sources:     SourceMap::new(),
+            resolver:    Resolver::new(),
+            checker:     Checker::new(),
+            interpreter: Interpreter::new(),
+            entries:     0
+        }
+    }
+
+    pub fn run(&mut self) {
+        let stdin = std::io::stdin();
+        let mut input = stdin.lock();
+        let mut buffer = String::new();
+
+        loop {
+            print!("{}", if buffer.is_empty() { "> " } else { ". " });
+            let _ = std::io::stdout().flush();
+
+            let mut line = String::new();
+            match input.read_line(&mut line) {
+                Ok(0) => {
+                    println!();
+                    return;
+                },
+                Ok(_) => {},
+                Err(e) => {
+                    eprintln!("error: cannot read standard input: {}", e);
+                    return;
+                }
+            }
+
+            if buffer.is_empty() && line.trim_start().starts_with(':') {
+                if !self.command(line.trim()) {
+                    return;
+                }
+                continue;
+            }
+
+            let give_up = line.trim().is_empty();
+            buffer.push_str(&line);
+
+            if buffer.trim().is_empty() {
+                buffer.clear();
+                continue;
+            }
+
+            match self.compile(&buffer, give_up) {
+                Chunk::Complete(program) => {
+                    self.eval(&program);
+                },
+                Chunk::Incomplete => continue,
+                Chunk::Failed => {}
+            }
+
+            buffer.clear();
+        }
+    }
+
+    /// Runs a `:` command, returns `false` once the REPL should stop.
+    fn command(&mut self, line: &str) -> bool {
+        let (name, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+
+        match name {
+            ":tokens" => self.tokens(rest),
+            ":ast" => {
+                if let Chunk::Complete(program) = self.compile(rest, true) {
+                    for stmt in &program.statements {
+                        println!("{}", stmt.kind);
+                    }
+                }
+            },
+            ":env" => {
+                for (name, value) in self.interpreter.env.bindings() {
+                    println!("{} = {} : {}", name, value, value.type_name());
+                }
+            },
+            ":reset" => {
+                self.resolver = Resolver::new();
+                self.checker = Checker::new();
+                self.interpreter = Interpreter::new();
+            },
+            ":help" => println!("{}", HELP),
+            ":quit" | ":q" => return false,
+            _ => eprintln!("unknown command `{}`, try `:help`", name)
+        }
+
+        true
+    }
+
+    fn add(&mut self, text: &str) -> usize {
+        self.entries+=1;
+        self.sources.add(&format!("<repl:{}>", self.entries), text)
+    }
+
+    fn tokens(&mut self, text: &str) {
+        let file_id = self.add(text);
+
+        match lex(self.sources.get(file_id), true) {
+            Ok(tokens) => tokens.iter().for_each(|token| println!("{}", token.describe(self.sources.get(file_id)))),
+            Err(e) => eprint!("{}", e.to_diagnostic().render(&self.sources, use_colour()))
+        }
+    }
+
+    /// Lexes and parses `text`. Unless `force` is set, errors that more input
+    /// could fix are not reported and ask for another line instead.
+    fn compile(&mut self, text: &str, force: bool) -> Chunk {
+        let file_id = self.add(text);
+
+        let mut lexer = Lexer::new(self.sources.get(file_id), false);
+        let (program, errors) = parse(&mut lexer);
+
+        match lexer.error() {
+            Some(e) if !force && e.is_incomplete() => return self.retract(),
+            Some(e) => {
+                eprint!("{}", e.to_diagnostic().render(&self.sources, use_colour()));
+                return Chunk::Failed;
+            },
+            None => {}
+        }
+
+        if errors.is_empty() {
+            return Chunk::Complete(program);
+        }
+
+        if !force && errors.iter().any(|e| e.is_incomplete()) {
+            return self.retract();
+        }
+
+        for e in &errors {
+            eprint!("{}", e.to_diagnostic().render(&self.sources, use_colour()));
+        }
+
+        Chunk::Failed
+    }
+
+    /// The source just added will be entered again with more lines, so its number is reused.
+    fn retract(&mut self) -> Chunk {
+        self.entries-=1;
+        Chunk::Incomplete
+    }
+
+    /// Resolves, checks and runs `program`, returns whether it got to the end.
+    fn eval(&mut self, program: &Program) -> bool {
+        let (resolution, errors) = self.resolver.resolve(program);
+
+        if !errors.is_empty() {
+            for e in &errors {
+                eprint!("{}", e.to_diagnostic().render(&self.sources, use_colour()));
+            }
+            return false;
+        }
+
+        let errors = self.checker.check(program, &resolution);
+
+        if !errors.is_empty() {
+            for e in &errors {
+                eprint!("{}", e.to_diagnostic().render(&self.sources, use_colour()));
+            }
+            return false;
+        }
+
+        let value = match self.interpreter.run(program) {
+            Ok(value) => value,
+            Err(e) => {
+                eprint!("{}", e.to_diagnostic().render(&self.sources, use_colour()));
+                return false;
+            }
+        };
+
+        // Only an entry that ran to the end keeps its names.
+        self.resolver.commit();
+        self.checker.commit();
+
+        if !matches!(value, Value::Unit) {
+            println!("{} : {}", value, value.type_name());
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Enters `text` as one complete entry, returns whether it ran.
+    fn enter(repl: &mut Repl, text: &str) -> bool {
+        match repl.compile(text, true) {
+            Chunk::Complete(program) => repl.eval(&program),
+            Chunk::Incomplete | Chunk::Failed => false
+        }
+    }
+
+    fn names(repl: &Repl) -> Vec<String> {
+        repl.interpreter.env.bindings().iter().map(|(name, _)| name.to_string()).collect()
+    }
+
+    #[test]
+    fn bindings_persist() {
+        let mut repl = Repl::new();
+
+        assert!(enter(&mut repl, "let a = 1"));
+        assert!(enter(&mut repl, "fn f() { a + 1 }"));
+        assert!(enter(&mut repl, "let b: int = f()"));
+        assert_eq!(names(&repl), vec!["a", "b", "f"]);
+    }
+
+    #[test]
+    fn failed_entries_are_rolled_back() {
+        let mut repl = Repl::new();
+
+        assert!(enter(&mut repl, "let a = 1"));
+        // Fails at runtime after binding `b` and `a` again.
+        assert!(!enter(&mut repl, "let b = 2\nlet a = \"s\"\n1 / 0"));
+        assert_eq!(names(&repl), vec!["a"]);
+        assert!(!enter(&mut repl, "b"));
+        assert!(enter(&mut repl, "let c: int = a + 1"));
+        // Fails in the checker, so `d` never gets a type.
+        assert!(!enter(&mut repl, "let d = 1\nd + \"s\""));
+        assert!(enter(&mut repl, "let d = \"s\""));
+        assert_eq!(names(&repl), vec!["a", "c", "d"]);
+    }
+
+    #[test]
+    fn unfinished_entries_ask_for_more() {
+        let mut repl = Repl::new();
+
+        assert!(matches!(repl.compile("let a = (1 +", false), Chunk::Incomplete));
+        assert!(matches!(repl.compile("let a = (1 +\n2)", false), Chunk::Complete(_)));
+        assert!(matches!(repl.compile("let a = (1 +", true), Chunk::Failed));
+    }
+}
@@ -0,0 +1,433 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+
+use crate::ast::{Expr, ExprKind, FnDef, Ident, Program, Stmt, StmtKind, SyntaxNode};
+use crate::error::ResolveError;
+use crate::span::Span;
+use crate::symbol::Symbol;
+
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq)]
+pub enum DeclarationKind {
+    Let,
+    Fn,
+    Param,
+    LoopVar
+}
+
+impl Display for DeclarationKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let what = match self {
+            DeclarationKind::Let => "variable",
+            DeclarationKind::Fn => "function",
+            DeclarationKind::Param => "parameter",
+            DeclarationKind::LoopVar => "loop variable"
+        };
+        write!(f, "{}", what)
+    }
+}
+
+/// The place a name was bound, `span` being that of the name itself.
+/// Only `let mut` declarations are `mutable`.
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct Declaration {
+    pub name:    Symbol,
+    pub span:    Span,
+    pub kind:    DeclarationKind,
+    pub mutable: bool
+}
+
+/// Every identifier use of a program, keyed by its span, with the declaration it refers to.
+#[derive(Debug)]
+#[derive(Default)]
+pub struct Resolution {
+    pub uses: HashMap<Span, Declaration>
+}
+
+/// A name a function body uses that was not defined yet where the function was.
+/// `fn_level` is that of the scope the name was declared in.
+#[derive(Clone, Copy)]
+#[derive(PartialEq)]
+struct Capture {
+    name:     Symbol,
+    span:     Span,
+    fn_level: usize
+}
+
+/// A name's declaration in a scope; `defined` turns true once execution has reached it.
+/// For a function, `captures` are the names it needs defined before it can be used.
+struct Binding {
+    declaration: Declaration,
+    defined:     bool,
+    captures:    Vec<Capture>
+}
+
+/// Names of one block, function body or loop. `fn_level` is the number of functions
+/// the scope is nested in.
+struct Scope {
+    bindings: HashMap<Symbol, Binding>,
+    fn_level: usize
+}
+
+impl Scope {
+    fn new(fn_level: usize) -> Self { Self { bindings: HashMap::new(), fn_level } }
+}
+
+/// Links identifier uses to declarations before anything runs. The rules:
+///
+/// - `{ ... }`, function bodies and loop bodies each open a scope; `if` branches too.
+/// - A name may be declared once per scope, but may shadow one from an enclosing scope.
+/// - Outside of functions, a name can only be used after its declaration. Inside a function
+///   body, names declared later in the enclosing scopes are fine, since the body runs when called.
+/// - Such a function, declared with `fn` or bound by `let`, can in turn only be used once
+///   those names are defined, and so can functions using it. Any other `fn` counts as used where it is.
+/// - Only names declared with `let mut` can be assigned to.
+///
+/// Top-level names stay in the resolver between calls to `resolve` once `commit` keeps them,
+/// and a later program may declare them again, which is what the REPL relies on.
+/// `captures` has an entry for each function body being resolved, the innermost last.
+pub struct Resolver {
+    globals:    Scope,
+    pending:    Option<Scope>,
+    scopes:     Vec<Scope>,
+    fn_level:   usize,
+    captures:   Vec<Vec<Capture>>,
+    resolution: Resolution,
+    errors:     Vec<ResolveError>
+}
+
+impl Default for Resolver {
+    fn default() -> Self { Self::new() }
+}
+
+impl Resolver {
+    pub fn new() -> Self {
+        Self {
+            globals:    Scope::new(0),
+            pending:    None,
+            scopes:     Vec::new(),
+            fn_level:   0,
+            captures:   Vec::new(),
+            resolution: Resolution::default(),
+            errors:     Vec::new()
+        }
+    }
+
+    /// Resolves every use in `program`. Its top-level names only become globals for later calls
+    /// when there were no errors and `commit` is called before the next `resolve`.
+    pub fn resolve(&mut self, program: &Program) -> (Resolution, Vec<ResolveError>) {
+        self.block(&program.statements);
+
+        let program_scope = self.scopes.pop().expect("`block` leaves its scope for the caller");
+        let errors = std::mem::take(&mut self.errors);
+
+        self.pending = errors.is_empty().then_some(program_scope);
+        (std::mem::take(&mut self.resolution), errors)
+    }
+
+    /// Keeps the top-level names of the program last resolved, once it is known to have run.
+    pub fn commit(&mut self) {
+        if let Some(scope) = self.pending.take() {
+            self.globals.bindings.extend(scope.bindings);
+        }
+    }
+
+    /// Resolves `statements` in a new scope and leaves it on the stack.
+    fn block(&mut self, statements: &[Stmt]) {
+        self.scopes.push(Scope::new(self.fn_level));
+
+        // Everything the block declares is known up front, so a use before the declaration
+        // can be told apart from a name that does not exist.
+        for stmt in statements {
+            match &stmt.kind {
+                StmtKind::Let { name, mutable, .. } => self.declare(name, DeclarationKind::Let, *mutable, false),
+                StmtKind::Fn(def) => self.declare(def.name.as_ref().unwrap(), DeclarationKind::Fn, false, false),
+                _ => {}
+            }
+        }
+
+        for stmt in statements {
+            self.statement(stmt);
+        }
+    }
+
+    fn scoped_block(&mut self, statements: &[Stmt]) {
+        self.block(statements);
+        self.scopes.pop();
+    }
+
+    fn declare(&mut self, name: &Ident, kind: DeclarationKind, mutable: bool, defined: bool) {
+        let scope = self.scopes.last_mut().expect("declarations are always inside a scope");
+        let declaration = Declaration { name: name.kind, span: name.span, kind, mutable };
+
+        if let Some(previous) = scope.bindings.get(&name.kind) {
+            self.errors.push(ResolveError::Redefined {
+                name:     name.kind.to_string(),
+                previous: previous.declaration.span,
+                span:     name.span
+            });
+            return;
+        }
+
+        scope.bindings.insert(name.kind, Binding { declaration, defined, captures: Vec::new() });
+    }
+
+    fn define(&mut self, name: &Ident, captures: Vec<Capture>) {
+        if let Some(binding) = self.scopes.last_mut().and_then(|s| s.bindings.get_mut(&name.kind)) {
+            binding.defined = true;
+            binding.captures = captures;
+        }
+    }
+
+    fn statement(&mut self, stmt: &Stmt) {
+        match &stmt.kind {
+            StmtKind::Let { name, value, .. } => {
+                let captures = match &value.kind {
+                    ExprKind::Fn(def) => self.function(def),
+                    _ => {
+                        self.expression(value);
+                        Vec::new()
+                    }
+                };
+                self.define(name, captures);
+            },
+            StmtKind::Assign { target, value, .. } => {
+                self.expression(value);
+
+                if let Some(declaration) = self.lookup(target.kind, target) {
+                    if !declaration.mutable {
+                        self.errors.push(ResolveError::Immutable {
+                            name:     target.kind.to_string(),
+                            kind:     declaration.kind,
+                            declared: declaration.span,
+                            span:     target.span
+                        });
+                    }
+                }
+            },
+            StmtKind::Fn(def) => {
+                // Defined before the body, so the function can call itself.
+                let name = def.name.as_ref().unwrap();
+                self.define(name, Vec::new());
+
+                let captures = self.function(def);
+                self.define(name, captures);
+            },
+            StmtKind::Return(value) => {
+                if let Some(value) = value {
+                    self.expression(value);
+                }
+            },
+            StmtKind::Break | StmtKind::Continue => {},
+            StmtKind::Expr(expr) => self.expression(expr)
+        }
+    }
+
+    /// Resolves the body of `def` and returns what it captures.
+    fn function(&mut self, def: &FnDef) -> Vec<Capture> {
+        self.fn_level+=1;
+        self.scopes.push(Scope::new(self.fn_level));
+        self.captures.push(Vec::new());
+
+        for param in &def.params {
+            self.declare(&param.name, DeclarationKind::Param, false, true);
+        }
+        self.scoped_block(&def.body);
+
+        self.scopes.pop();
+        self.fn_level-=1;
+        self.captures.pop().expect("pushed above")
+    }
+
+    fn expression(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Int(_) | ExprKind::Float(_) | ExprKind::Str(_) | ExprKind::Char(_) | ExprKind::Bool(_) => {},
+            ExprKind::Ident(name) => {
+                self.lookup(*name, expr);
+            },
+            ExprKind::Unary { operand, .. } => self.expression(operand),
+            ExprKind::Binary { lhs, rhs, .. } => {
+                self.expression(lhs);
+                self.expression(rhs);
+            },
+            ExprKind::Call { callee, args } => {
+                self.expression(callee);
+                args.iter().for_each(|arg| self.expression(arg));
+            },
+            ExprKind::Fn(def) => {
+                let captures = self.function(def);
+                self.require(None, &captures, expr.span);
+            },
+            ExprKind::Block(statements) => self.scoped_block(statements),
+            ExprKind::If { cond, then, otherwise } => {
+                self.expression(cond);
+                self.scoped_block(then);
+                if let Some(otherwise) = otherwise {
+                    self.scoped_block(otherwise);
+                }
+            },
+            ExprKind::While { cond, body } => {
+                self.expression(cond);
+                self.scoped_block(body);
+            },
+            ExprKind::For { var, iter, body } => {
+                self.expression(iter);
+
+                self.scopes.push(Scope::new(self.fn_level));
+                self.declare(var, DeclarationKind::LoopVar, false, true);
+                self.scoped_block(body);
+                self.scopes.pop();
+            }
+        }
+    }
+
+    /// Links the use `node` of `name` to the innermost declaration that is already defined,
+    /// or declared at all in a scope outside the current function, and returns it.
+    fn lookup<K>(&mut self, name: Symbol, node: &SyntaxNode<K>) -> Option<Declaration> {
+        let mut later = None;
+        let mut found = None;
+
+        for scope in self.scopes.iter().rev().chain(std::iter::once(&self.globals)) {
+            let Some(binding) = scope.bindings.get(&name) else {
+                continue;
+            };
+
+            if binding.defined || scope.fn_level < self.fn_level {
+                let mut captures = binding.captures.clone();
+                if !binding.defined {
+                    captures.push(Capture { name, span: binding.declaration.span, fn_level: scope.fn_level });
+                }
+                found = Some((binding.declaration.clone(), captures));
+                break;
+            }
+            later.get_or_insert(binding.declaration.span);
+        }
+
+        if let Some((declaration, captures)) = found {
+            self.resolution.uses.insert(node.span, declaration.clone());
+            self.require(Some(name), &captures, node.span);
+            return Some(declaration);
+        }
+
+        self.errors.push(match later {
+            Some(declared) => ResolveError::UsedBeforeDefinition {
+                name: name.to_string(),
+                declared,
+                span: node.span
+            },
+            None => ResolveError::Undefined { name: name.to_string(), span: node.span }
+        });
+        None
+    }
+    /// `used` (`None` for an anonymous function), at `span`, needs everything in `captures` defined,
+    /// as well as whatever the functions among them capture in turn. Those declared at this function
+    /// level have to be by now; the others become captures of the function bodies between here
+    /// and where they were declared, to be checked where those are used.
+    fn require(&mut self, used: Option<Symbol>, captures: &[Capture], span: Span) {
+        let mut pending = captures.to_vec();
+        let mut seen = Vec::new();
+
+        while let Some(capture) = pending.pop() {
+            if seen.contains(&capture) {
+                continue;
+            }
+            seen.push(capture);
+
+            if capture.fn_level < self.fn_level {
+                for frame in &mut self.captures[capture.fn_level..] {
+                    if !frame.contains(&capture) {
+                        frame.push(capture);
+                    }
+                }
+                continue;
+            }
+
+            match self.binding(&capture) {
+                Some(binding) if !binding.defined => self.errors.push(ResolveError::CapturedBeforeDefinition {
+                    name:     used.map(|name| name.to_string()),
+                    captured: capture.name.to_string(),
+                    declared: capture.span,
+                    span
+                }),
+                Some(binding) => pending.extend(binding.captures.iter().copied()),
+                None => {}
+            }
+        }
+    }
+
+    /// The binding `capture` refers to, if its scope is still open.
+    fn binding(&self, capture: &Capture) -> Option<&Binding> {
+        self.scopes.iter().rev().chain(std::iter::once(&self.globals))
+            .filter(|scope| scope.fn_level == capture.fn_level)
+            .find_map(|scope| scope.bindings.get(&capture.name).filter(|b| b.declaration.span == capture.span))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::span::SourceMap;
+    use crate::testing::Stage;
+    use crate::{lex, parse, testing};
+
+    fn errors(text: &str) -> Vec<&'static str> { testing::errors(&[text], Stage::Resolve).remove(0) }
+
+    #[test]
+    fn scopes() {
+        assert_eq!(errors("let x = 1\n{ let x = \"s\"\nx }\nx"), Vec::<&str>::new());
+        assert_eq!(errors("x"), vec!["S0001"]);
+        assert_eq!(errors("{ let y = 1 }\ny"), vec!["S0001"]);
+        assert_eq!(errors("x\nlet x = 1"), vec!["S0002"]);
+        assert_eq!(errors("let x = 1\nlet x = 2"), vec!["S0003"]);
+    }
+
+    #[test]
+    fn assignment() {
+        assert_eq!(errors("let mut x = 1\nx += 1\nx = 3"), Vec::<&str>::new());
+        assert_eq!(errors("let x = 1\nx = 2"), vec!["S0004"]);
+        assert_eq!(errors("fn f(a) { a = 1 }"), vec!["S0004"]);
+        assert_eq!(errors("fn f() { }\nf = fn() { }"), vec!["S0004"]);
+    }
+
+    #[test]
+    fn functions_may_use_later_names() {
+        assert_eq!(errors("fn f() { g() }\nfn g() { 1 }\nf()"), Vec::<&str>::new());
+        assert_eq!(errors("fn even(n) { if n == 0 { true } else { odd(n - 1) } }\n\
+                           fn odd(n) { if n == 0 { false } else { even(n - 1) } }\n\
+                           even(4)"), Vec::<&str>::new());
+        assert_eq!(errors("let h = fn() { z }\nlet z = 2\nh()"), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn captures_before_definition() {
+        assert_eq!(errors("fn f() { y }\nf()\nlet y be 1"), vec!["S0005"]);
+        assert_eq!(errors("let x be 1\n{ fn f() { x }\nlet r be f()\nlet x be \"s\"\nr + \"t\" }"), vec!["S0005"]);
+        // Through a function that is itself declared later.
+        assert_eq!(errors("fn g() { f() }\nfn f() { y }\ng()\nlet y = 1"), vec!["S0005"]);
+        // Through a function declared inside another one.
+        assert_eq!(errors("fn f() { fn g() { u }\ng }\nlet h = f()\nlet u = 3"), vec!["S0005"]);
+        assert_eq!(errors("(fn() { w })()\nlet w = 1"), vec!["S0005"]);
+        assert_eq!(errors("fn f() { fn g() { q }\ng()\nlet q = 1\n0 }"), vec!["S0005"]);
+        assert_eq!(errors("fn f() { fn g() { q }\nlet q = 1\ng() }\nf()"), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn globals_are_kept_once_committed() {
+        assert_eq!(testing::errors(&["let a = 1", "a"], Stage::Resolve), vec![Vec::<&str>::new(), vec![]]);
+        assert_eq!(testing::errors(&["let a = 1\nb", "a"], Stage::Resolve), vec![vec!["S0001"], vec!["S0001"]]);
+
+        let mut sources = SourceMap::new();
+        let mut resolver = Resolver::new();
+        let mut resolve = |text: &str| {
+            let file_id = sources.add("test", text);
+            let (program, _) = parse(lex(sources.get(file_id), false).unwrap());
+            resolver.resolve(&program).1.len()
+        };
+
+        assert_eq!(resolve("let a = 1"), 0);
+        // Never committed, so `a` is gone.
+        assert_eq!(resolve("a"), 1);
+    }
+}
@@ -0,0 +1,127 @@
+use std::ops::Range;
+use std::rc::Rc;
+
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::Pos;
+
+/// Byte range `start..end` into the text of the file registered under `file_id`.
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq, Eq, Hash)]
+pub struct Span {
+    pub file_id: usize,
+    pub start:   usize,
+    pub end:     usize
+}
+
+impl Span {
+    pub fn make(file_id: usize, start: usize, end: usize) -> Self { Self { file_id, start, end } }
+
+    /// Smallest span covering both `self` and `other`.
+    pub fn to(&self, other: Span) -> Span {
+        Span::make(self.file_id, self.start.min(other.start), self.end.max(other.end))
+    }
+
+    pub fn range(&self) -> Range<usize> { self.start..self.end }
+}
+
+/// One source text plus the byte offset every line starts at,
+/// so offsets can be turned back into rows and columns without rescanning.
+pub struct SourceFile {
+    pub id:      usize,
+    pub name:    Rc<str>,
+    pub text:    String,
+    line_starts: Vec<usize>
+}
+
+impl SourceFile {
+    pub fn make(id: usize, name: String, text: String) -> Self {
+        let mut line_starts = vec![0];
+        let bytes = text.as_bytes();
+
+        for (i, b) in bytes.iter().enumerate() {
+            match b {
+                b'\r' if bytes.get(i + 1) == Some(&b'\n') => {},
+                b'\n' | b'\r' => line_starts.push(i + 1),
+                _ => {}
+            }
+        }
+
+        Self { id, name: name.into(), text, line_starts }
+    }
+
+    pub fn slice(&self, span: Span) -> &str { &self.text[span.range()] }
+
+    /// 1-based row and columns of the byte at `offset`.
+    pub fn pos(&self, offset: usize) -> Pos {
+        let line = match self.line_starts.binary_search(&offset) {
+            Ok(line) => line,
+            Err(next) => next - 1
+        };
+        let before = &self.text[self.line_starts[line]..offset];
+
+        Pos::make(self.name.clone(),
+                  line + 1,
+                  before.graphemes(true).count() + 1,
+                  before.len() + 1,
+                  before.encode_utf16().count() + 1)
+    }
+
+    /// Text of the 1-based `row`, without its line break.
+    pub fn line(&self, row: usize) -> &str {
+        let start = self.line_starts[row - 1];
+        let end = self.line_starts.get(row).copied().unwrap_or(self.text.len());
+
+        self.text[start..end].trim_end_matches(['\n', '\r'])
+    }
+}
+
+/// Owns every file handed to the lexer; a `Span`'s `file_id` indexes into it.
+#[derive(Default)]
+pub struct SourceMap {
+    files: Vec<SourceFile>
+}
+
+impl SourceMap {
+    pub fn new() -> Self { Self::default() }
+
+    pub fn add(&mut self, name: &str, text: &str) -> usize {
+        let id = self.files.len();
+        self.files.push(SourceFile::make(id, name.to_string(), text.to_string()));
+        id
+    }
+
+    pub fn get(&self, file_id: usize) -> &SourceFile { &self.files[file_id] }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rows_and_lines() {
+        let file = SourceFile::make(0, "test".to_string(), "ab\r\ncd\ne\rf".to_string());
+        let pos = |offset| {
+            let pos = file.pos(offset);
+            (pos.row, pos.col)
+        };
+
+        assert_eq!(pos(0), (1, 1));
+        assert_eq!(pos(2), (1, 3));
+        assert_eq!(pos(4), (2, 1));
+        assert_eq!(pos(8), (3, 2));
+        assert_eq!(pos(9), (4, 1));
+        assert_eq!((file.line(1), file.line(2), file.line(3), file.line(4)), ("ab", "cd", "e", "f"));
+        assert_eq!(file.slice(Span::make(0, 4, 6)), "cd");
+    }
+
+    #[test]
+    fn unicode_columns() {
+        // `e` plus a combining accent is one grapheme of three bytes; `😀` is four bytes and two UTF-16 units.
+        let file = SourceFile::make(0, "test".to_string(), "e\u{301}😀x".to_string());
+        let pos = file.pos(7);
+
+        assert_eq!((pos.col, pos.byte_col, pos.utf16_col), (3, 8, 5));
+    }
+}
@@ -0,0 +1,86 @@
+//! How much of the current thread's stack is left. Parsing, resolving, checking and running
+//! all recurse over the syntax tree, and a deep enough tree or a runaway recursion would
+//! otherwise overflow the stack, which aborts the whole process instead of reporting an error.
+
+use std::cell::Cell;
+
+/// What `exhausted` keeps free: enough for any pass to go one more level down
+/// and then unwind with an error, in a debug build.
+const RESERVE: usize = 256 << 10;
+
+/// Stack assumed to be left below the first frame that asks, when the thread's real limit cannot
+/// be found out. Threads the standard library starts get 2 MiB, so whatever ran before has room.
+const FALLBACK: usize = 1 << 20;
+
+/// The main thread's stack limit when `ulimit -s` says unlimited.
+#[cfg(target_os = "linux")]
+const UNLIMITED: usize = 8 << 20;
+
+thread_local! {
+    /// Lowest address this thread's stack may grow down to, found out on first use.
+    static LIMIT: Cell<Option<usize>> = const { Cell::new(None) };
+}
+
+/// Bytes of stack left below the caller's frame.
+pub fn remaining() -> usize {
+    let here = address();
+    let limit = LIMIT.with(|limit| match limit.get() {
+        Some(address) => address,
+        None => {
+            let address = find_limit(here);
+            limit.set(Some(address));
+            address
+        }
+    });
+
+    here.saturating_sub(limit)
+}
+
+/// Whether the stack is too close to its end to go another level deeper.
+pub fn exhausted() -> bool { remaining() < RESERVE }
+
+/// Roughly where the stack ends right now: the address of a local in a frame of its own.
+#[inline(never)]
+fn address() -> usize {
+    let marker = 0u8;
+    std::hint::black_box(&marker) as *const u8 as usize
+}
+
+#[cfg(target_os = "linux")]
+fn find_limit(here: usize) -> usize { mapped_limit(here).unwrap_or(here.saturating_sub(FALLBACK)) }
+
+#[cfg(not(target_os = "linux"))]
+fn find_limit(here: usize) -> usize { here.saturating_sub(FALLBACK) }
+
+/// The bottom of the mapping `here` lies in. A thread's stack is mapped whole when it starts,
+/// with a guard page of its own below it; only the main thread's `[stack]` grows on demand,
+/// up to the `Max stack size` resource limit below its top.
+#[cfg(target_os = "linux")]
+fn mapped_limit(here: usize) -> Option<usize> {
+    let maps = std::fs::read_to_string("/proc/self/maps").ok()?;
+
+    for line in maps.lines() {
+        let (range, rest) = line.split_once(' ')?;
+        let (low, high) = range.split_once('-')?;
+        let (low, high) = (usize::from_str_radix(low, 16).ok()?, usize::from_str_radix(high, 16).ok()?);
+
+        if !(low..high).contains(&here) {
+            continue;
+        }
+
+        if !rest.ends_with("[stack]") {
+            return Some(low);
+        }
+
+        let limits = std::fs::read_to_string("/proc/self/limits").ok()?;
+        let size = limits.lines()
+            .find_map(|line| line.strip_prefix("Max stack size"))?
+            .split_whitespace()
+            .next()?;
+        let size = if size == "unlimited" { UNLIMITED } else { size.parse().ok()? };
+
+        return Some(high.saturating_sub(size));
+    }
+
+    None
+}
@@ -0,0 +1,59 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::{Debug, Display, Formatter};
+use std::marker::PhantomData;
+use std::rc::Rc;
+
+/// An interned name. Comparing and hashing one is comparing a `u32`,
+/// which is what scopes do on every lookup.
+/// The `u32` only means something to the interner of the thread that made it,
+/// so a `Symbol` cannot be sent to another thread.
+#[derive(Clone, Copy)]
+#[derive(PartialEq, Eq, Hash)]
+pub struct Symbol(u32, PhantomData<*const ()>);
+
+/// Every name interned so far on this thread; they are dropped along with the thread.
+#[derive(Default)]
+struct Interner {
+    ids:   HashMap<Rc<str>, Symbol>,
+    names: Vec<Rc<str>>
+}
+
+thread_local! {
+    static INTERNER: RefCell<Interner> = RefCell::new(Interner::default());
+}
+
+impl Symbol {
+    /// The symbol for `name`; only allocates the first time a name is seen.
+    pub fn intern(name: &str) -> Symbol {
+        INTERNER.with(|interner| {
+            let mut interner = interner.borrow_mut();
+
+            if let Some(symbol) = interner.ids.get(name) {
+                return *symbol;
+            }
+
+            let symbol = Symbol(interner.names.len() as u32, PhantomData);
+            let name: Rc<str> = name.into();
+            interner.names.push(name.clone());
+            interner.ids.insert(name, symbol);
+            symbol
+        })
+    }
+
+    pub fn name(self) -> Rc<str> {
+        INTERNER.with(|interner| interner.borrow().names[self.0 as usize].clone())
+    }
+}
+
+impl Debug for Symbol {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.name())
+    }
+}
+
+impl Display for Symbol {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
@@ -0,0 +1,62 @@
+//! Takes source text through the stages the way the REPL does, for the tests of each stage.
+
+use crate::span::SourceMap;
+use crate::value::Value;
+use crate::{lex, parse, Checker, Interpreter, Resolver};
+
+/// How far `run_all` takes each program.
+#[derive(Clone, Copy)]
+#[derive(PartialEq, PartialOrd)]
+pub enum Stage {
+    Resolve,
+    Check,
+    Run
+}
+
+/// Takes each of `programs` in turn through the stages up to `last` with one resolver, checker
+/// and interpreter, committing the ones without errors like the REPL does.
+/// Each result is the program's value as the REPL prints it, empty for `Unit` or if it was not run,
+/// or the codes of the errors of the stage that stopped it.
+pub fn run_all(programs: &[&str], last: Stage) -> Vec<Result<String, Vec<&'static str>>> {
+    let mut sources = SourceMap::new();
+    let mut resolver = Resolver::new();
+    let mut checker = Checker::new();
+    let mut interpreter = Interpreter::new();
+
+    programs.iter().map(|text| {
+        let file_id = sources.add("test", text);
+        let (program, errors) = parse(lex(sources.get(file_id), false).unwrap());
+        assert!(errors.is_empty(), "parse errors in {:?}", text);
+
+        let (resolution, errors) = resolver.resolve(&program);
+        if !errors.is_empty() {
+            return Err(errors.iter().map(|e| e.code()).collect());
+        }
+
+        let mut value = String::new();
+        if last >= Stage::Check {
+            let errors = checker.check(&program, &resolution);
+            if !errors.is_empty() {
+                return Err(errors.iter().map(|e| e.code()).collect());
+            }
+        }
+        if last == Stage::Run {
+            value = match interpreter.run(&program).map_err(|e| vec![e.code()])? {
+                Value::Unit => String::new(),
+                other => other.to_string()
+            };
+        }
+
+        resolver.commit();
+        checker.commit();
+        Ok(value)
+    }).collect()
+}
+
+/// The error codes of each of `programs`, empty for those that went through, see `run_all`.
+pub fn errors(programs: &[&str], last: Stage) -> Vec<Vec<&'static str>> {
+    run_all(programs, last).into_iter().map(|result| result.err().unwrap_or_default()).collect()
+}
+
+/// The value of `text` run on its own, or the codes of the errors that stopped it.
+pub fn run(text: &str) -> Result<String, Vec<&'static str>> { run_all(&[text], Stage::Run).remove(0) }
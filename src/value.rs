@@ -0,0 +1,94 @@
+use std::fmt::{Debug, Display, Formatter};
+use std::rc::Rc;
+
+use crate::ast::FnDef;
+use crate::eval::Environment;
+use crate::symbol::Symbol;
+
+/// Result of evaluating an expression.
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+pub enum Value {
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    Str(String),
+    Char(char),
+    Function(Rc<Closure>),
+    /// Integers from `start` up to but not including `end`, as written `start..end`.
+    Range(i64, i64),
+    Unit
+}
+
+/// A function value: its definition plus the scope it was created in.
+pub struct Closure {
+    pub def: Rc<FnDef>,
+    pub env: Environment
+}
+
+impl Closure {
+    pub fn name(&self) -> Option<Symbol> { self.def.name.as_ref().map(|n| n.kind) }
+}
+
+/// Two closures are equal only if they are the same definition captured in the same scope.
+impl PartialEq for Closure {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.def, &other.def) && self.env.ptr_eq(&other.env)
+    }
+}
+
+/// Printing the captured scope could recurse forever, since it usually contains the closure itself.
+impl Debug for Closure {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+impl Display for Closure {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.name() {
+            Some(name) => write!(f, "<fn {}>", name),
+            None => write!(f, "<fn>")
+        }
+    }
+}
+
+impl Value {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Int(_) => "int",
+            Value::Float(_) => "float",
+            Value::Bool(_) => "bool",
+            Value::Str(_) => "string",
+            Value::Char(_) => "char",
+            Value::Function(_) => "fn",
+            Value::Range(..) => "range",
+            Value::Unit => "unit"
+        }
+    }
+
+    /// Integers widen to floats so mixed arithmetic works, everything else has no numeric value.
+    pub fn as_float(&self) -> Option<f64> {
+        match self {
+            Value::Int(n) => Some(*n as f64),
+            Value::Float(n) => Some(*n),
+            _ => None
+        }
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Int(n) => write!(f, "{}", n),
+            Value::Float(n) => write!(f, "{:?}", n),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Str(s) => write!(f, "{}", s),
+            Value::Char(c) => write!(f, "{}", c),
+            Value::Function(closure) => write!(f, "{}", closure),
+            Value::Range(start, end) => write!(f, "{}..{}", start, end),
+            Value::Unit => write!(f, "()")
+        }
+    }
+}